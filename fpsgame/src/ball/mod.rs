@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use bevy::prelude::*;
+
+use crate::{movement::{Collider, Gravity, RigidBody}, physics::primitive::Sphere, weapon::Projectile};
+
+/// pull of gravity on balls with a gravity scale of 1
+const GRAVITY: f32 = 10.0;
+/// color of balls that are not projectiles
+pub const BALL_COLOR: [f32; 3] = [0.5, 0.5, 0.5];
+
+/// Mesh and material handles shared by all balls of the same size and color
+#[derive(Debug, Default)]
+pub struct BallMeshes(HashMap<(u32, [u32; 3]), (Handle<Mesh>, Handle<StandardMaterial>)>);
+
+impl BallMeshes {
+    /// the handles for balls of `radius` and `color`, created the first time they are asked for
+    pub fn get(
+        &mut self,
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<StandardMaterial>,
+        radius: f32,
+        [r, g, b]: [f32; 3],
+    ) -> (Handle<Mesh>, Handle<StandardMaterial>) {
+        let key = (radius.to_bits(), [r.to_bits(), g.to_bits(), b.to_bits()]);
+        self.0.entry(key).or_insert_with(|| (
+            meshes.add(Mesh::from(shape::Icosphere { radius, subdivisions: 3 })),
+            materials.add(Color::rgb(r, g, b).into()),
+        )).clone()
+    }
+}
+
+/// Spawns a rendered rigid body with a sphere collider, more components can be added to the returned `Commands`
+pub fn spawn_ball(
+    commands: &mut Commands,
+    (mesh, material): (Handle<Mesh>, Handle<StandardMaterial>),
+    body: RigidBody,
+    radius: f32,
+    gravity_scale: f32,
+) -> &mut Commands {
+    let position = body.position;
+    // gravity is applied as a force
+    let gravity = Gravity(Vec3::new(0.0, -GRAVITY * gravity_scale * body.mass, 0.0));
+
+    commands
+        .spawn(PbrComponents {
+            mesh,
+            material,
+            transform: Transform::from_translation(position),
+            ..Default::default()
+        })
+        .with(body)
+        .with(Collider {
+            sphere: Sphere::new(Vec3::zero(), radius),
+        })
+        .with(gravity)
+}
+
+/// Gives balls spawned without a mesh, like bodies respawned by a snapshot, the mesh they were spawned with
+pub fn add_ball_meshes(
+    mut commands: Commands,
+    mut ball_meshes: ResMut<BallMeshes>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    balls: Query<(Entity, &RigidBody, &Collider, Option<&Projectile>, Option<&Handle<Mesh>>)>,
+) {
+    for (entity, body, collider, projectile, mesh) in balls.iter() {
+        if mesh.is_some() {
+            continue;
+        }
+
+        let color = projectile.map(|projectile| projectile.definition.color).unwrap_or(BALL_COLOR);
+        let (mesh, material) = ball_meshes.get(&mut meshes, &mut materials, collider.sphere.radius, color);
+        commands.insert(entity, PbrComponents {
+            mesh,
+            material,
+            transform: Transform::from_translation(body.position),
+            ..Default::default()
+        });
+    }
+}
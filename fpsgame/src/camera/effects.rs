@@ -0,0 +1,147 @@
+use std::ops::{Add, Mul};
+use bevy::prelude::*;
+
+use crate::{
+    math::degrees_to_radians,
+    movement::{CharacterVelocity, GroundedState, MovementData, MovementMode, Stamina},
+    player::Player,
+    profile::MovementProfile,
+};
+use super::MainCamera;
+
+/// sprinting bobs harder, but not without limit
+const MAX_BOB_WEIGHT: f32 = 1.5;
+/// how fast the bob fades in and out when starting or stopping
+const BOB_BLEND_SPEED: f32 = 10.0;
+
+/// Added on top of the view of the camera, never changes where the player aims
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CameraOffset {
+    /// radians in the same directions as the yaw and pitch of the player, positive pitch looks down
+    pub yaw: f32,
+    pub pitch: f32,
+    pub roll: f32,
+    /// relative to the eye, x to the side, y up and z forward
+    pub position: Vec3,
+    /// radians added to the field of view
+    pub fov: f32,
+}
+
+impl Add for CameraOffset {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            yaw: self.yaw + other.yaw,
+            pitch: self.pitch + other.pitch,
+            roll: self.roll + other.roll,
+            position: self.position + other.position,
+            fov: self.fov + other.fov,
+        }
+    }
+}
+
+impl Mul<f32> for CameraOffset {
+    type Output = Self;
+
+    fn mul(self, factor: f32) -> Self {
+        Self {
+            yaw: self.yaw * factor,
+            pitch: self.pitch * factor,
+            roll: self.roll * factor,
+            position: self.position * factor,
+            fov: self.fov * factor,
+        }
+    }
+}
+
+/// Every effect is its own layer, the camera shows the sum of all of them. The shake is set by `shake_cameras`.
+#[derive(Debug, Default)]
+pub struct CameraEffects {
+    pub shake: CameraOffset,
+    pub recoil: CameraOffset,
+    pub fov_kick: CameraOffset,
+    /// widens the view while sprinting
+    pub sprint: CameraOffset,
+    pub landing: CameraOffset,
+    pub bob: CameraOffset,
+
+    bob_phase: f32,
+    bob_weight: f32,
+    /// vertical speed of the last frame, the velocity is already zeroed in the frame the player lands
+    last_vertical_speed: f32,
+}
+
+impl CameraEffects {
+    pub fn total(&self) -> CameraOffset {
+        self.shake + self.recoil + self.fov_kick + self.sprint + self.landing + self.bob
+    }
+
+    /// kicks the view up by `pitch` and to the side by `yaw` radians, it recovers on its own
+    pub fn add_recoil(&mut self, pitch: f32, yaw: f32) {
+        self.recoil.pitch -= pitch;
+        self.recoil.yaw += yaw;
+    }
+
+    /// widens the view by `radians` for a moment
+    pub fn kick_fov(&mut self, radians: f32) {
+        self.fov_kick.fov += radians;
+    }
+}
+
+/// factor that is left of a value decaying at `rate` per second after `delta_seconds`
+fn recovery(rate: f32, delta_seconds: f32) -> f32 {
+    (-rate * delta_seconds).exp()
+}
+
+// --- Runs before the camera is placed ---
+
+pub fn update_camera_effects(
+    time: Res<Time>,
+    profiles: Res<Assets<MovementProfile>>,
+    player_query: Query<(&Player, &MovementData, &GroundedState, &MovementMode, &CharacterVelocity, Option<&Stamina>, &Handle<MovementProfile>)>,
+    mut camera_query: Query<(&MainCamera, &mut CameraEffects)>,
+) {
+    let delta = time.delta_seconds;
+
+    for (_, movement_data, grounded_state, mode, velocity, stamina, profile) in player_query.iter() {
+        let profile = MovementProfile::get(&profiles, profile);
+        let settings = &profile.effects;
+
+        for (_, mut effects) in camera_query.iter_mut() {
+            // weapons kick in `fire_weapons`, this only recovers
+            effects.recoil = effects.recoil * recovery(settings.recoil_recovery, delta);
+            effects.fov_kick = effects.fov_kick * recovery(settings.fov_kick_recovery, delta);
+
+            let is_sprinting = stamina.map(|stamina| stamina.is_sprinting).unwrap_or(false);
+            let sprint_fov = if is_sprinting { degrees_to_radians(profile.camera.sprint_fov - profile.camera.fov) } else { 0.0 };
+            effects.sprint.fov += (sprint_fov - effects.sprint.fov) * (profile.camera.fov_speed * delta).min(1.0);
+
+            // the harder the fall the deeper the dip
+            if grounded_state.is_grounded && !grounded_state.was_grounded {
+                let fall_speed = (-effects.last_vertical_speed).max(0.0);
+                let dip = (effects.landing.position.y() - fall_speed * settings.landing_dip).max(-settings.max_landing_dip);
+                effects.landing.position.set_y(dip);
+            }
+            effects.last_vertical_speed = velocity.0.y();
+            effects.landing = effects.landing * recovery(settings.landing_recovery, delta);
+
+            let horizontal_speed = Vec3::new(velocity.0.x(), 0.0, velocity.0.z()).length();
+            let target_weight = if *mode == MovementMode::Walking && grounded_state.is_grounded {
+                (horizontal_speed / movement_data.max_speed).min(MAX_BOB_WEIGHT)
+            } else {
+                0.0
+            };
+            effects.bob_weight += (target_weight - effects.bob_weight) * (BOB_BLEND_SPEED * delta).min(1.0);
+
+            // a full cycle is two steps, the view dips on every step and sways from one foot to the other
+            effects.bob_phase = (effects.bob_phase + horizontal_speed / settings.bob_step_length * std::f32::consts::PI * delta) % (2.0 * std::f32::consts::PI);
+            let (phase, weight) = (effects.bob_phase, effects.bob_weight);
+            effects.bob = CameraOffset {
+                roll: phase.sin() * degrees_to_radians(settings.bob_roll) * weight,
+                position: Vec3::new(phase.sin() * settings.bob_sway, -(2.0 * phase).sin().abs() * settings.bob_height, 0.0) * weight,
+                ..Default::default()
+            };
+        }
+    }
+}
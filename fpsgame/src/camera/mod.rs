@@ -0,0 +1,116 @@
+use bevy::{prelude::*, render::camera::{Camera, CameraProjection, PerspectiveProjection}};
+
+use crate::{
+    math::degrees_to_radians,
+    physics,
+    player::{MAX_PITCH, Player, look_direction},
+    profile::MovementProfile,
+};
+
+mod effects;
+mod rig;
+mod shake;
+
+pub use effects::*;
+pub use rig::*;
+pub use shake::*;
+
+/// keeps the near plane of the camera out of walls
+const CAMERA_RADIUS: f32 = 0.25;
+const THIRD_PERSON_DISTANCE: f32 = 4.0;
+const SPECTATOR_DISTANCE: f32 = 6.0;
+/// the spectator looks at this point above the origin of its target
+const SPECTATOR_HEIGHT: f32 = 1.0;
+
+/// The camera the world is rendered with
+pub struct MainCamera;
+
+/// where a camera orbiting `center` at `distance` ends up, it is pulled in front of anything in between
+pub fn orbit_position(world: &physics::World, center: Vec3, direction: Vec3, distance: f32) -> Vec3 {
+    center - direction * world.sweep_sphere(center, -direction, CAMERA_RADIUS, distance)
+}
+
+// --- Runs once the player moved and all camera effects are updated ---
+
+/// places the camera for its mode, the effects are added on top of the aim of the player
+pub fn update_camera(
+    world: Res<physics::World>,
+    profiles: Res<Assets<MovementProfile>>,
+    player_query: Query<(&Player, &Transform, &Handle<MovementProfile>)>,
+    target_query: Query<(Entity, &SpectatorTarget, &Transform)>,
+    mut camera_query: Query<(&MainCamera, &CameraRig, &CameraEffects, &mut Camera, &mut PerspectiveProjection, &mut Transform)>,
+) {
+    for (player, player_transform, profile) in player_query.iter() {
+        let settings = &MovementProfile::get(&profiles, profile).camera;
+        let eye = player_transform.translation + (Vec3::unit_y() * player.camera_height);
+
+        for (_, rig, effects, mut camera, mut projection, mut transform) in camera_query.iter_mut() {
+            // bobbing and recoil belong to the player, detached cameras only shake
+            let offset = if rig.mode.is_detached() { effects.shake } else { effects.total() };
+            let (yaw, pitch) = if rig.mode.is_detached() { (rig.yaw, rig.pitch) } else { (player.yaw, player.pitch) };
+            let direction = look_direction(yaw + offset.yaw, (pitch + offset.pitch).max(-MAX_PITCH).min(MAX_PITCH));
+
+            // the position offset follows the yaw only, so looking up and down does not tilt the bob
+            let forward = look_direction(yaw, 0.0);
+            let side = forward.cross(Vec3::unit_y());
+            let position_offset = side * offset.position.x() + Vec3::unit_y() * offset.position.y() + forward * offset.position.z();
+
+            let camera_position = match rig.mode {
+                CameraMode::FirstPerson => eye + position_offset,
+                CameraMode::ThirdPerson => orbit_position(&world, eye + position_offset, direction, THIRD_PERSON_DISTANCE),
+                CameraMode::FreeFly => rig.position,
+                CameraMode::Spectator => {
+                    let center = target_query.iter()
+                        .find(|(entity, ..)| Some(*entity) == rig.target)
+                        .map(|(_, _, target_transform)| target_transform.translation + Vec3::unit_y() * SPECTATOR_HEIGHT)
+                        .unwrap_or(eye);
+                    orbit_position(&world, center, direction, SPECTATOR_DISTANCE)
+                },
+            };
+
+            *transform = Transform::from_translation(camera_position).looking_at(camera_position + direction, Vec3::unit_y());
+            transform.rotation = transform.rotation * Quat::from_rotation_z(offset.roll);
+
+            let fov = degrees_to_radians(settings.fov) + offset.fov;
+            if (projection.fov - fov).abs() > std::f32::EPSILON {
+                projection.fov = fov;
+                camera.projection_matrix = projection.get_projection_matrix();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::Vec3;
+
+    use crate::physics::{self, test_util};
+    use super::*;
+
+    fn create_wall_world() -> physics::World {
+        // wall facing +z at z = -2
+        physics::create_world_from_triangles(test_util::quad(
+            Vec3::new(-10.0, -10.0, -2.0),
+            Vec3::new(10.0, -10.0, -2.0),
+            Vec3::new(10.0, 10.0, -2.0),
+            Vec3::new(-10.0, 10.0, -2.0),
+        ))
+    }
+
+    #[test]
+    fn test_orbit_stops_in_front_of_wall() {
+        let world = create_wall_world();
+        let position = orbit_position(&world, Vec3::zero(), Vec3::unit_z(), THIRD_PERSON_DISTANCE);
+
+        assert!(position.z() > -2.0 + CAMERA_RADIUS * 0.9, "{:?}", position);
+        assert!(position.z() < -2.0 + CAMERA_RADIUS * 1.1, "{:?}", position);
+    }
+
+    #[test]
+    fn test_orbit_keeps_distance_in_the_open() {
+        let world = create_wall_world();
+        let position = orbit_position(&world, Vec3::zero(), -Vec3::unit_z(), THIRD_PERSON_DISTANCE);
+
+        assert_eq!(position, Vec3::new(0.0, 0.0, THIRD_PERSON_DISTANCE));
+    }
+}
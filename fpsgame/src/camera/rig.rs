@@ -0,0 +1,149 @@
+use bevy::prelude::*;
+
+use crate::{
+    controls::{Action, ActionState, Axis},
+    player::{MAX_PITCH, Player, look_direction, mouse_rotation},
+    profile::MovementProfile,
+};
+
+const FREE_FLY_SPEED: f32 = 15.0;
+const FREE_FLY_SPRINT_FACTOR: f32 = 3.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    FirstPerson,
+    /// orbits behind the player without going through walls
+    ThirdPerson,
+    /// debug camera that flies on its own
+    FreeFly,
+    /// orbits around one of the `SpectatorTarget`s
+    Spectator,
+}
+
+impl CameraMode {
+    pub fn next(self) -> Self {
+        match self {
+            CameraMode::FirstPerson => CameraMode::ThirdPerson,
+            CameraMode::ThirdPerson => CameraMode::FreeFly,
+            CameraMode::FreeFly => CameraMode::Spectator,
+            CameraMode::Spectator => CameraMode::FirstPerson,
+        }
+    }
+
+    /// detached cameras take the input, the player keeps simulating as if nothing was pressed
+    pub fn is_detached(self) -> bool {
+        match self {
+            CameraMode::FirstPerson | CameraMode::ThirdPerson => false,
+            CameraMode::FreeFly | CameraMode::Spectator => true,
+        }
+    }
+}
+
+/// Something the spectator camera can watch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SpectatorTarget {
+    Enemy,
+    Pet,
+    Tower,
+}
+
+/// How the camera follows the game, detached cameras keep their own position and rotation
+#[derive(Debug)]
+pub struct CameraRig {
+    pub mode: CameraMode,
+    pub position: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// the spectated entity, the player is watched while there is none
+    pub target: Option<Entity>,
+}
+
+impl Default for CameraRig {
+    fn default() -> Self {
+        Self {
+            mode: CameraMode::FirstPerson,
+            position: Vec3::zero(),
+            yaw: 0.0,
+            pitch: 0.0,
+            target: None,
+        }
+    }
+}
+
+/// true while a detached camera takes the input away from the player
+pub fn is_camera_detached(rig_query: &Query<&CameraRig>) -> bool {
+    rig_query.iter().any(|rig| rig.mode.is_detached())
+}
+
+/// the entity after `current`, sorted by kind so enemies, pets and towers are visited in groups
+fn next_target(current: Option<Entity>, mut targets: Vec<(SpectatorTarget, Entity)>) -> Option<Entity> {
+    targets.sort_by_key(|(kind, entity)| (*kind, entity.to_bits()));
+    let index = current
+        .and_then(|current| targets.iter().position(|(_, entity)| *entity == current))
+        .map(|index| index + 1)
+        .unwrap_or(0);
+    targets.get(index).or_else(|| targets.first()).map(|(_, entity)| *entity)
+}
+
+// --- Runs after the actions are updated and before the player reads them ---
+
+pub fn control_camera_rig(
+    time: Res<Time>,
+    actions: Res<ActionState>,
+    profiles: Res<Assets<MovementProfile>>,
+    player_query: Query<(&Player, &Handle<MovementProfile>)>,
+    target_query: Query<(Entity, &SpectatorTarget)>,
+    mut rig_query: Query<(&mut CameraRig, &Transform)>,
+) {
+    let (player_yaw, player_pitch, look_settings) = match player_query.iter().next() {
+        Some((player, profile)) => (player.yaw, player.pitch, MovementProfile::get(&profiles, profile).look.clone()),
+        None => (0.0, 0.0, MovementProfile::default().look),
+    };
+
+    for (mut rig, transform) in rig_query.iter_mut() {
+        if actions.just_pressed(Action::SwitchCamera) {
+            // detached cameras start from the current view, the player itself is left untouched
+            rig.mode = rig.mode.next();
+            rig.position = transform.translation;
+            rig.yaw = player_yaw;
+            rig.pitch = player_pitch;
+            println!("Camera mode {:?}", rig.mode);
+        }
+
+        if !rig.mode.is_detached() {
+            continue;
+        }
+
+        let counts = Vec2::new(actions.axis(Axis::LookX), actions.axis(Axis::LookY));
+        let turn = Vec2::new(actions.axis(Axis::TurnX), actions.axis(Axis::TurnY)) * time.delta_seconds;
+        let rotation = mouse_rotation(counts, &look_settings, 1.0, 1.0) + turn;
+        rig.yaw += rotation.x();
+        rig.pitch = (rig.pitch + rotation.y()).max(-MAX_PITCH).min(MAX_PITCH);
+
+        match rig.mode {
+            CameraMode::FreeFly => {
+                // same directions as walking, but along the pitch as well
+                let forward = look_direction(rig.yaw, rig.pitch);
+                let left = Vec3::new(rig.yaw.cos(), 0.0, rig.yaw.sin());
+                let up = actions.pressed(Action::Jump) as i32 as f32 - actions.pressed(Action::Descend) as i32 as f32;
+                let speed = if actions.pressed(Action::Sprint) { FREE_FLY_SPEED * FREE_FLY_SPRINT_FACTOR } else { FREE_FLY_SPEED };
+
+                let direction = forward * actions.axis(Axis::MoveForward) + left * actions.axis(Axis::MoveLeft) + Vec3::unit_y() * up;
+                rig.position += direction * speed * time.delta_seconds;
+            },
+            CameraMode::Spectator => {
+                let targets: Vec<(SpectatorTarget, Entity)> = target_query.iter().map(|(entity, kind)| (*kind, entity)).collect();
+                let target_exists = rig.target.map(|target| targets.iter().any(|(_, entity)| *entity == target)).unwrap_or(false);
+
+                if actions.just_pressed(Action::NextTarget) || !target_exists {
+                    let target = next_target(if target_exists { rig.target } else { None }, targets);
+                    if target != rig.target {
+                        println!("Spectating {:?}", target);
+                    }
+                    rig.target = target;
+                }
+            },
+            CameraMode::FirstPerson | CameraMode::ThirdPerson => {},
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use bevy::prelude::*;
+use noise::{NoiseFn, Perlin, Seedable};
+use serde::{Deserialize, Serialize};
+
+use super::{CameraEffects, CameraOffset};
+
+const MAX_TRAUMA: f32 = 1.0;
+/// cameras spawned with `CameraShake::default` shake the same way every run
+const DEFAULT_SEED: u32 = 7;
+
+/// How a kind of shake feels
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShakeProfile {
+    /// noise samples per second, higher values shake faster
+    pub frequency: f64,
+    /// trauma lost per second
+    pub decay: f32,
+    /// trauma is raised to this power, so small amounts of trauma barely shake
+    pub power: f32,
+    /// maximum rotation in radians around each axis at full trauma
+    pub yaw: f32,
+    pub pitch: f32,
+    pub roll: f32,
+}
+
+impl Default for ShakeProfile {
+    fn default() -> Self {
+        Self {
+            frequency: 10.0,
+            decay: 2.0,
+            power: 2.0,
+            yaw: 0.2,
+            pitch: 0.1,
+            roll: 0.1,
+        }
+    }
+}
+
+/// Shakes every camera within `radius` of `origin`, the trauma falls off linearly with the distance
+#[derive(Debug, Clone, Copy)]
+pub struct ShakeEvent {
+    pub origin: Vec3,
+    /// trauma added to a camera right at the origin, 1 is the most a camera can take
+    pub intensity: f32,
+    pub radius: f32,
+    pub profile: ShakeProfile,
+}
+
+impl ShakeEvent {
+    pub fn trauma_at(&self, position: Vec3) -> f32 {
+        let falloff = 1.0 - (position - self.origin).length() / self.radius.max(std::f32::EPSILON);
+        self.intensity * falloff.max(0.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Shake {
+    trauma: f32,
+    profile: ShakeProfile,
+}
+
+/// The trauma of a camera, shakes with the same profile add up while different profiles shake on their own
+pub struct CameraShake {
+    shakes: Vec<Shake>,
+    noise: Perlin,
+    time: f64,
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEED)
+    }
+}
+
+impl CameraShake {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            shakes: Vec::new(),
+            noise: Perlin::new().set_seed(seed),
+            time: 0.0,
+        }
+    }
+
+    pub fn add_trauma(&mut self, trauma: f32, profile: ShakeProfile) {
+        if trauma <= 0.0 {
+            return;
+        }
+
+        match self.shakes.iter_mut().find(|shake| shake.profile == profile) {
+            Some(shake) => shake.trauma = (shake.trauma + trauma).min(MAX_TRAUMA),
+            None => self.shakes.push(Shake { trauma: trauma.min(MAX_TRAUMA), profile }),
+        }
+    }
+
+    /// the highest trauma of all shakes
+    pub fn trauma(&self) -> f32 {
+        self.shakes.iter().map(|shake| shake.trauma).fold(0.0, f32::max)
+    }
+
+    /// trauma and profile of every shake together with the noise time, what a snapshot needs to shake the same again
+    pub(crate) fn state(&self) -> (Vec<(f32, ShakeProfile)>, f64) {
+        (self.shakes.iter().map(|shake| (shake.trauma, shake.profile)).collect(), self.time)
+    }
+
+    pub(crate) fn set_state(&mut self, shakes: &[(f32, ShakeProfile)], time: f64) {
+        self.shakes = shakes.iter().map(|&(trauma, profile)| Shake { trauma, profile }).collect();
+        self.time = time;
+    }
+
+    pub fn update(&mut self, delta_seconds: f32) {
+        self.time += delta_seconds as f64;
+        for shake in self.shakes.iter_mut() {
+            shake.trauma -= shake.profile.decay * delta_seconds;
+        }
+        self.shakes.retain(|shake| shake.trauma > 0.0);
+    }
+
+    pub fn offset(&self) -> CameraOffset {
+        let mut offset = CameraOffset::default();
+
+        for (index, shake) in self.shakes.iter().enumerate() {
+            let amount = shake.trauma.powf(shake.profile.power);
+            let time = self.time * shake.profile.frequency;
+            // every axis and shake samples its own row of the noise
+            let row = index as f64 * 3.0;
+            offset.yaw += shake.profile.yaw * amount * self.noise.get([row + 1.0, time]) as f32;
+            offset.pitch += shake.profile.pitch * amount * self.noise.get([row + 2.0, time]) as f32;
+            offset.roll += shake.profile.roll * amount * self.noise.get([row + 3.0, time]) as f32;
+        }
+
+        offset
+    }
+}
+
+// --- Runs before the camera is placed ---
+
+pub fn shake_cameras(
+    mut shake_reader: Local<EventReader<ShakeEvent>>,
+    shake_events: Res<Events<ShakeEvent>>,
+    time: Res<Time>,
+    mut camera_query: Query<(&Transform, &mut CameraShake, &mut CameraEffects)>,
+) {
+    let events: Vec<ShakeEvent> = shake_reader.iter(&shake_events).copied().collect();
+
+    for (transform, mut shake, mut effects) in camera_query.iter_mut() {
+        for event in events.iter() {
+            shake.add_trauma(event.trauma_at(transform.translation), event.profile);
+        }
+
+        shake.update(time.delta_seconds);
+        effects.shake = shake.offset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::Vec3;
+
+    use super::*;
+
+    fn event(intensity: f32) -> ShakeEvent {
+        ShakeEvent {
+            origin: Vec3::zero(),
+            intensity,
+            radius: 10.0,
+            profile: ShakeProfile::default(),
+        }
+    }
+
+    #[test]
+    fn test_trauma_falls_off_with_distance() {
+        let event = event(0.8);
+
+        assert_eq!(event.trauma_at(Vec3::zero()), 0.8);
+        assert!((event.trauma_at(Vec3::new(5.0, 0.0, 0.0)) - 0.4).abs() < 1e-6);
+        assert_eq!(event.trauma_at(Vec3::new(0.0, 12.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn test_trauma_adds_up_without_overshooting() {
+        let mut shake = CameraShake::default();
+        shake.add_trauma(0.5, ShakeProfile::default());
+        assert_eq!(shake.trauma(), 0.5);
+
+        shake.add_trauma(0.25, ShakeProfile::default());
+        assert_eq!(shake.trauma(), 0.75);
+
+        shake.add_trauma(0.5, ShakeProfile::default());
+        assert_eq!(shake.trauma(), 1.0);
+
+        shake.update(0.25);
+        assert_eq!(shake.trauma(), 0.5);
+        shake.update(1.0);
+        assert_eq!(shake.offset(), CameraOffset::default());
+    }
+
+    #[test]
+    fn test_restored_state_shakes_the_same() {
+        let mut shake = CameraShake::default();
+        shake.add_trauma(0.8, ShakeProfile::default());
+        shake.update(0.1);
+        let (shakes, time) = shake.state();
+
+        let mut restored = CameraShake::default();
+        restored.set_state(&shakes, time);
+        shake.update(0.1);
+        restored.update(0.1);
+
+        assert_eq!(restored.offset(), shake.offset());
+    }
+}
@@ -0,0 +1,292 @@
+use std::{collections::HashMap, error::Error, fs};
+use bevy::{input::gamepad::{Gamepad, GamepadAxisType, GamepadButtonType, GamepadEvent, GamepadEventType}, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use super::{Action, Axis, GamepadState, Stick, StickDirection, StickSettings, gamepad_button_from_name, gamepad_button_name};
+
+pub const BINDINGS_PATH: &str = "./bindings.ron";
+
+/// Generates the conversion between key codes and the names used in the bindings file
+macro_rules! named_keys {
+    ($($key:ident),* $(,)?) => {
+        pub fn key_from_name(name: &str) -> Option<KeyCode> {
+            match name {
+                $(stringify!($key) => Some(KeyCode::$key),)*
+                _ => None,
+            }
+        }
+
+        pub fn key_name(key: KeyCode) -> Option<&'static str> {
+            match key {
+                $(KeyCode::$key => Some(stringify!($key)),)*
+                _ => None,
+            }
+        }
+
+        /// every key that can be bound
+        pub const BINDABLE_KEYS: &[KeyCode] = &[$(KeyCode::$key),*];
+    };
+}
+
+named_keys!(
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+    Space, LShift, RShift, LControl, RControl, LAlt, RAlt, Tab, Return, Back,
+    Up, Down, Left, Right,
+    Insert, Delete, Home, End,
+    Grave, Minus, Equals, LBracket, RBracket, Semicolon, Apostrophe, Comma, Period, Slash, Backslash,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+);
+
+pub fn mouse_button_from_name(name: &str) -> Option<MouseButton> {
+    match name {
+        "Left" => Some(MouseButton::Left),
+        "Right" => Some(MouseButton::Right),
+        "Middle" => Some(MouseButton::Middle),
+        _ => name.strip_prefix("Button").and_then(|number| number.parse().ok()).map(MouseButton::Other),
+    }
+}
+
+pub fn mouse_button_name(button: MouseButton) -> String {
+    match button {
+        MouseButton::Left => "Left".to_string(),
+        MouseButton::Right => "Right".to_string(),
+        MouseButton::Middle => "Middle".to_string(),
+        MouseButton::Other(number) => format!("Button{}", number),
+    }
+}
+
+/// A single input that can trigger an action, devices are referred to by name so the file stays readable
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(String),
+    Mouse(String),
+    Gamepad(String),
+}
+
+impl Binding {
+    pub fn key(key: KeyCode) -> Self {
+        Binding::Key(key_name(key).unwrap_or("Unknown").to_string())
+    }
+
+    pub fn mouse(button: MouseButton) -> Self {
+        Binding::Mouse(mouse_button_name(button))
+    }
+
+    pub fn gamepad(button: GamepadButtonType) -> Self {
+        Binding::Gamepad(gamepad_button_name(button).to_string())
+    }
+}
+
+/// Everything bindings are read from this frame
+pub struct Devices<'a> {
+    pub keys: &'a Input<KeyCode>,
+    pub mouse_buttons: &'a Input<MouseButton>,
+    /// mouse motion in counts since the last frame
+    pub mouse_motion: Vec2,
+    pub gamepad: &'a GamepadState,
+}
+
+/// The devices of a single frame, referred to by name like in the bindings. Demos record them and replay
+/// them through the bindings they were recorded with.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceSnapshot {
+    /// keys and buttons held down
+    pub keys: Vec<String>,
+    pub mouse_buttons: Vec<String>,
+    /// mouse motion in counts since the last frame
+    pub mouse_motion: (f32, f32),
+    /// triggers are pulled gradually
+    pub gamepad_buttons: Vec<(String, f32)>,
+    pub left_stick: (f32, f32),
+    pub right_stick: (f32, f32),
+}
+
+impl DeviceSnapshot {
+    pub fn capture(devices: &Devices) -> Self {
+        let mut mouse_buttons: Vec<String> = devices.mouse_buttons.get_pressed().map(|button| mouse_button_name(*button)).collect();
+        mouse_buttons.sort();
+        let mut gamepad_buttons: Vec<(String, f32)> = devices.gamepad.held_buttons()
+            .map(|(button, value)| (gamepad_button_name(button).to_string(), value))
+            .collect();
+        gamepad_buttons.sort_by(|a, b| a.0.cmp(&b.0));
+        let (left_stick, right_stick) = (devices.gamepad.stick(Stick::Left), devices.gamepad.stick(Stick::Right));
+
+        Self {
+            keys: BINDABLE_KEYS.iter().filter(|key| devices.keys.pressed(**key)).filter_map(|key| key_name(*key)).map(str::to_string).collect(),
+            mouse_buttons,
+            mouse_motion: (devices.mouse_motion.x(), devices.mouse_motion.y()),
+            gamepad_buttons,
+            left_stick: (left_stick.x(), left_stick.y()),
+            right_stick: (right_stick.x(), right_stick.y()),
+        }
+    }
+
+    /// calls `f` with devices in the captured state
+    pub fn replay<R>(&self, f: impl FnOnce(&Devices) -> R) -> R {
+        let mut keys = Input::default();
+        for key in self.keys.iter().filter_map(|name| key_from_name(name)) {
+            keys.press(key);
+        }
+        let mut mouse_buttons = Input::default();
+        for button in self.mouse_buttons.iter().filter_map(|name| mouse_button_from_name(name)) {
+            mouse_buttons.press(button);
+        }
+
+        // a gamepad that sends the captured values
+        let mut gamepad = GamepadState::default();
+        let mut send = |event_type| gamepad.handle_event(&GamepadEvent(Gamepad(0), event_type));
+        send(GamepadEventType::Connected);
+        for (name, value) in self.gamepad_buttons.iter() {
+            if let Some(button) = gamepad_button_from_name(name) {
+                send(GamepadEventType::ButtonChanged(button, *value));
+            }
+        }
+        send(GamepadEventType::AxisChanged(GamepadAxisType::LeftStickX, self.left_stick.0));
+        send(GamepadEventType::AxisChanged(GamepadAxisType::LeftStickY, self.left_stick.1));
+        send(GamepadEventType::AxisChanged(GamepadAxisType::RightStickX, self.right_stick.0));
+        send(GamepadEventType::AxisChanged(GamepadAxisType::RightStickY, self.right_stick.1));
+
+        f(&Devices {
+            keys: &keys,
+            mouse_buttons: &mouse_buttons,
+            mouse_motion: Vec2::new(self.mouse_motion.0, self.mouse_motion.1),
+            gamepad: &gamepad,
+        })
+    }
+}
+
+/// Where the value of an axis comes from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AxisBinding {
+    /// -1 while `negative` is held, 1 while `positive` is held
+    Buttons { negative: Binding, positive: Binding },
+    /// mouse motion in counts since the last frame
+    MouseX,
+    MouseY,
+    /// one direction of a stick after its deadzone and curve, multiplied by `scale`
+    Stick { stick: Stick, direction: StickDirection, scale: f32 },
+}
+
+/// Which inputs trigger which action, persisted in `bindings.ron`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputBindings {
+    pub actions: HashMap<Action, Vec<Binding>>,
+    pub axes: HashMap<Axis, Vec<AxisBinding>>,
+    pub left_stick: StickSettings,
+    pub right_stick: StickSettings,
+    /// how far analog triggers have to be pulled to count as pressed
+    pub trigger_threshold: f32,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        let mut actions = HashMap::new();
+        actions.insert(Action::Jump, vec![Binding::key(KeyCode::Space), Binding::gamepad(GamepadButtonType::South)]);
+        actions.insert(Action::Crouch, vec![Binding::key(KeyCode::LControl), Binding::gamepad(GamepadButtonType::East)]);
+        actions.insert(Action::Sprint, vec![Binding::key(KeyCode::LShift), Binding::gamepad(GamepadButtonType::LeftThumb)]);
+        actions.insert(Action::Descend, vec![Binding::key(KeyCode::LAlt), Binding::gamepad(GamepadButtonType::DPadDown)]);
+        actions.insert(Action::ToggleFly, vec![Binding::key(KeyCode::V), Binding::gamepad(GamepadButtonType::Select)]);
+        actions.insert(Action::Fire, vec![Binding::mouse(MouseButton::Left), Binding::gamepad(GamepadButtonType::RightTrigger2)]);
+        actions.insert(Action::Reload, vec![Binding::key(KeyCode::R), Binding::gamepad(GamepadButtonType::North)]);
+        actions.insert(Action::NextWeapon, vec![Binding::key(KeyCode::Q), Binding::gamepad(GamepadButtonType::RightTrigger)]);
+        actions.insert(Action::PreviousWeapon, vec![Binding::key(KeyCode::Z), Binding::gamepad(GamepadButtonType::LeftTrigger)]);
+        actions.insert(Action::Interact, vec![Binding::key(KeyCode::E), Binding::gamepad(GamepadButtonType::West)]);
+        actions.insert(Action::RotateHeld, vec![Binding::key(KeyCode::F), Binding::gamepad(GamepadButtonType::RightThumb)]);
+        actions.insert(Action::Build, vec![Binding::key(KeyCode::B), Binding::gamepad(GamepadButtonType::LeftTrigger2)]);
+        actions.insert(Action::Shake, vec![Binding::key(KeyCode::T)]);
+        actions.insert(Action::SwitchCamera, vec![Binding::key(KeyCode::C), Binding::gamepad(GamepadButtonType::DPadUp)]);
+        actions.insert(Action::NextTarget, vec![Binding::key(KeyCode::Tab), Binding::gamepad(GamepadButtonType::DPadRight)]);
+
+        let mut axes = HashMap::new();
+        axes.insert(Axis::MoveForward, vec![
+            AxisBinding::Buttons { negative: Binding::key(KeyCode::S), positive: Binding::key(KeyCode::W) },
+            AxisBinding::Stick { stick: Stick::Left, direction: StickDirection::Y, scale: 1.0 },
+        ]);
+        axes.insert(Axis::MoveLeft, vec![
+            AxisBinding::Buttons { negative: Binding::key(KeyCode::D), positive: Binding::key(KeyCode::A) },
+            AxisBinding::Stick { stick: Stick::Left, direction: StickDirection::X, scale: -1.0 },
+        ]);
+        axes.insert(Axis::LookX, vec![AxisBinding::MouseX]);
+        axes.insert(Axis::LookY, vec![AxisBinding::MouseY]);
+        // stick y points up while mouse y points down
+        axes.insert(Axis::TurnX, vec![AxisBinding::Stick { stick: Stick::Right, direction: StickDirection::X, scale: 1.0 }]);
+        axes.insert(Axis::TurnY, vec![AxisBinding::Stick { stick: Stick::Right, direction: StickDirection::Y, scale: -1.0 }]);
+
+        Self {
+            actions,
+            axes,
+            left_stick: StickSettings::default(),
+            // turning speed in radians per second, curved for precise aiming
+            right_stick: StickSettings {
+                curve: 2.0,
+                sensitivity: 3.0,
+                ..Default::default()
+            },
+            trigger_threshold: 0.5,
+        }
+    }
+}
+
+impl InputBindings {
+    pub fn is_pressed(&self, binding: &Binding, devices: &Devices) -> bool {
+        match binding {
+            Binding::Key(name) => key_from_name(name).map(|key| devices.keys.pressed(key)).unwrap_or(false),
+            Binding::Mouse(name) => mouse_button_from_name(name).map(|button| devices.mouse_buttons.pressed(button)).unwrap_or(false),
+            Binding::Gamepad(name) => gamepad_button_from_name(name).map(|button| devices.gamepad.button(button) >= self.trigger_threshold).unwrap_or(false),
+        }
+    }
+
+    pub fn axis_value(&self, binding: &AxisBinding, devices: &Devices) -> f32 {
+        match binding {
+            AxisBinding::Buttons { negative, positive } => {
+                let positive = self.is_pressed(positive, devices) as i32 as f32;
+                let negative = self.is_pressed(negative, devices) as i32 as f32;
+                positive - negative
+            },
+            AxisBinding::MouseX => devices.mouse_motion.x(),
+            AxisBinding::MouseY => devices.mouse_motion.y(),
+            AxisBinding::Stick { stick, direction, scale } => {
+                let settings = match stick {
+                    Stick::Left => &self.left_stick,
+                    Stick::Right => &self.right_stick,
+                };
+                let value = settings.apply(devices.gamepad.stick(*stick));
+                match direction {
+                    StickDirection::X => value.x() * scale,
+                    StickDirection::Y => value.y() * scale,
+                }
+            },
+        }
+    }
+
+    /// replaces everything bound to `action` with `binding`
+    pub fn rebind(&mut self, action: Action, binding: Binding) {
+        self.actions.insert(action, vec![binding]);
+    }
+
+    pub fn to_ron(&self) -> Result<String, Box<dyn Error>> {
+        Ok(ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.to_ron()?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(ron::de::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// the saved bindings, or the defaults if there are none yet
+    pub fn load_or_default(path: &str) -> Self {
+        match Self::load(path) {
+            Ok(bindings) => bindings,
+            Err(error) => {
+                println!("Using default bindings, could not read {}: {}", path, error);
+                Self::default()
+            },
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use bevy::{input::gamepad::{Gamepad, GamepadAxisType, GamepadButtonType, GamepadEvent, GamepadEventType}, prelude::*};
+use serde::{Deserialize, Serialize};
+
+/// Generates the conversion between gamepad buttons and the names used in the bindings file
+macro_rules! named_buttons {
+    ($($button:ident),* $(,)?) => {
+        pub fn gamepad_button_from_name(name: &str) -> Option<GamepadButtonType> {
+            match name {
+                $(stringify!($button) => Some(GamepadButtonType::$button),)*
+                _ => None,
+            }
+        }
+
+        pub fn gamepad_button_name(button: GamepadButtonType) -> &'static str {
+            match button {
+                $(GamepadButtonType::$button => stringify!($button),)*
+            }
+        }
+    };
+}
+
+named_buttons!(
+    South, East, North, West, C, Z,
+    LeftTrigger, LeftTrigger2, RightTrigger, RightTrigger2,
+    Select, Start, Mode, LeftThumb, RightThumb,
+    DPadUp, DPadDown, DPadLeft, DPadRight,
+);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Stick {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum StickDirection {
+    X,
+    Y,
+}
+
+/// How raw stick positions are turned into input
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StickSettings {
+    /// the stick counts as centered while it is closer than this to the center
+    pub inner_deadzone: f32,
+    /// the stick counts as fully pushed beyond this
+    pub outer_deadzone: f32,
+    /// exponent applied to the distance from the center, above 1 gives finer control near the center
+    pub curve: f32,
+    /// the output at full deflection
+    pub sensitivity: f32,
+}
+
+impl Default for StickSettings {
+    fn default() -> Self {
+        Self {
+            inner_deadzone: 0.15,
+            outer_deadzone: 0.95,
+            curve: 1.0,
+            sensitivity: 1.0,
+        }
+    }
+}
+
+impl StickSettings {
+    /// radial deadzone, so diagonals are not snapped to the axes
+    pub fn apply(&self, raw: Vec2) -> Vec2 {
+        let length = raw.length();
+        if length <= self.inner_deadzone {
+            return Vec2::zero();
+        }
+
+        let range = (self.outer_deadzone - self.inner_deadzone).max(std::f32::EPSILON);
+        let normalized = ((length - self.inner_deadzone) / range).min(1.0);
+        raw / length * normalized.powf(self.curve) * self.sensitivity
+    }
+}
+
+/// The values of the gamepad that controls the player. The first gamepad connected is used,
+/// if it is unplugged the next connected one takes over.
+#[derive(Debug, Default)]
+pub struct GamepadState {
+    pub active: Option<Gamepad>,
+    connected: Vec<Gamepad>,
+    axes: HashMap<GamepadAxisType, f32>,
+    buttons: HashMap<GamepadButtonType, f32>,
+}
+
+impl GamepadState {
+    pub fn handle_event(&mut self, event: &GamepadEvent) {
+        let GamepadEvent(gamepad, event_type) = event;
+
+        match event_type {
+            GamepadEventType::Connected => {
+                if !self.connected.contains(gamepad) {
+                    self.connected.push(*gamepad);
+                }
+                if self.active.is_none() {
+                    self.active = Some(*gamepad);
+                }
+            },
+            GamepadEventType::Disconnected => {
+                self.connected.retain(|connected| connected != gamepad);
+                if self.active == Some(*gamepad) {
+                    // the values of the new gamepad come in with its next events
+                    self.active = self.connected.first().copied();
+                    self.axes.clear();
+                    self.buttons.clear();
+                }
+            },
+            GamepadEventType::AxisChanged(axis, value) if self.active == Some(*gamepad) => {
+                self.axes.insert(*axis, *value);
+            },
+            GamepadEventType::ButtonChanged(button, value) if self.active == Some(*gamepad) => {
+                self.buttons.insert(*button, *value);
+            },
+            _ => {},
+        }
+    }
+
+    pub fn axis(&self, axis: GamepadAxisType) -> f32 {
+        self.axes.get(&axis).copied().unwrap_or(0.0)
+    }
+
+    /// 0 when released, triggers go up to 1 gradually
+    pub fn button(&self, button: GamepadButtonType) -> f32 {
+        self.buttons.get(&button).copied().unwrap_or(0.0)
+    }
+
+    /// every button that is not released
+    pub fn held_buttons(&self) -> impl Iterator<Item = (GamepadButtonType, f32)> + '_ {
+        self.buttons.iter().filter(|(_, value)| **value > 0.0).map(|(button, value)| (*button, *value))
+    }
+
+    /// raw position of the stick, before any deadzone
+    pub fn stick(&self, stick: Stick) -> Vec2 {
+        match stick {
+            Stick::Left => Vec2::new(self.axis(GamepadAxisType::LeftStickX), self.axis(GamepadAxisType::LeftStickY)),
+            Stick::Right => Vec2::new(self.axis(GamepadAxisType::RightStickX), self.axis(GamepadAxisType::RightStickY)),
+        }
+    }
+}
+
+// --- Runs first in the frame, before the actions are updated ---
+
+pub fn track_gamepads(
+    mut gamepad_reader: Local<EventReader<GamepadEvent>>,
+    gamepad_events: Res<Events<GamepadEvent>>,
+    mut gamepad_state: ResMut<GamepadState>,
+) {
+    for event in gamepad_reader.iter(&gamepad_events) {
+        match event.1 {
+            GamepadEventType::Connected => println!("Gamepad {:?} connected", event.0),
+            GamepadEventType::Disconnected => println!("Gamepad {:?} disconnected", event.0),
+            _ => {},
+        }
+        gamepad_state.handle_event(event);
+    }
+}
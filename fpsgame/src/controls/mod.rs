@@ -0,0 +1,372 @@
+use std::collections::{HashMap, HashSet};
+use bevy::{input::mouse::MouseMotion, prelude::*};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::camera::{CameraRig, is_camera_detached};
+
+mod bindings;
+mod gamepad;
+
+pub use bindings::*;
+pub use gamepad::*;
+
+/// Something the player can do, gameplay code asks for these instead of specific keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    Jump,
+    Crouch,
+    Sprint,
+    /// moves down while flying
+    Descend,
+    ToggleFly,
+    Fire,
+    Reload,
+    NextWeapon,
+    PreviousWeapon,
+    /// picks up and drops bodies with the physics gun
+    Interact,
+    /// mouse motion turns the held body instead of the view
+    RotateHeld,
+    Build,
+    /// debug camera shake
+    Shake,
+    /// cycles through first person, third person, free fly and spectator cameras
+    SwitchCamera,
+    /// the next thing to watch in spectator mode
+    NextTarget,
+}
+
+/// every action in the order they are offered for rebinding
+pub const ACTIONS: &[Action] = &[
+    Action::Jump,
+    Action::Crouch,
+    Action::Sprint,
+    Action::Descend,
+    Action::ToggleFly,
+    Action::Fire,
+    Action::Reload,
+    Action::NextWeapon,
+    Action::PreviousWeapon,
+    Action::Interact,
+    Action::RotateHeld,
+    Action::Build,
+    Action::Shake,
+    Action::SwitchCamera,
+    Action::NextTarget,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Axis {
+    MoveForward,
+    MoveLeft,
+    /// mouse look in counts
+    LookX,
+    LookY,
+    /// stick look in radians per second
+    TurnX,
+    TurnY,
+}
+
+/// nothing pressed, what the player sees while a detached camera takes the input
+static NEUTRAL: Lazy<ActionState> = Lazy::new(ActionState::default);
+
+/// The state of all actions this frame
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    just_released: HashSet<Action>,
+    axes: HashMap<Axis, f32>,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn just_released(&self, action: Action) -> bool {
+        self.just_released.contains(&action)
+    }
+
+    pub fn axis(&self, axis: Axis) -> f32 {
+        self.axes.get(&axis).copied().unwrap_or(0.0)
+    }
+
+    /// the actions that were held before the last `update`
+    pub fn previous(&self) -> ActionState {
+        let mut pressed: HashSet<Action> = self.pressed.difference(&self.just_pressed).copied().collect();
+        pressed.extend(self.just_released.iter().copied());
+        ActionState {
+            pressed,
+            ..Default::default()
+        }
+    }
+
+    /// the actions that drive the player, the player keeps simulating without input while a detached camera flies on its own
+    pub fn for_player<'a>(&'a self, rig_query: &Query<&CameraRig>) -> &'a ActionState {
+        if is_camera_detached(rig_query) { &NEUTRAL } else { self }
+    }
+
+    /// sets the state directly, used by anything that is not a real device
+    pub fn set_pressed(&mut self, action: Action, pressed: bool) {
+        let was_pressed = self.pressed(action);
+        if pressed && !was_pressed {
+            self.pressed.insert(action);
+            self.just_pressed.insert(action);
+        } else if !pressed && was_pressed {
+            self.pressed.remove(&action);
+            self.just_released.insert(action);
+        }
+    }
+
+    pub fn set_axis(&mut self, axis: Axis, value: f32) {
+        self.axes.insert(axis, value);
+    }
+
+    /// reads all bound devices, `suppressed` treats everything as released
+    pub fn update(&mut self, bindings: &InputBindings, devices: &Devices, suppressed: bool) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+        self.axes.clear();
+
+        for action in ACTIONS {
+            let pressed = !suppressed && bindings.actions.get(action)
+                .map(|action_bindings| action_bindings.iter().any(|binding| bindings.is_pressed(binding, devices)))
+                .unwrap_or(false);
+            self.set_pressed(*action, pressed);
+        }
+
+        for (axis, axis_bindings) in bindings.axes.iter() {
+            let value = if suppressed {
+                0.0
+            } else {
+                axis_bindings.iter().map(|binding| bindings.axis_value(binding, devices)).sum()
+            };
+            self.set_axis(*axis, value);
+        }
+    }
+}
+
+/// Set to an index into `ACTIONS` while waiting for the input to bind to it
+#[derive(Debug, Default)]
+pub struct Rebinding(pub Option<usize>);
+
+// --- Runs first in the frame ---
+
+/// translates the devices into actions through the bindings
+pub fn update_action_state(
+    mut mouse_motion_reader: Local<EventReader<MouseMotion>>,
+    mouse_motion_events: Res<Events<MouseMotion>>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    gamepad: Res<GamepadState>,
+    bindings: Res<InputBindings>,
+    rebinding: Res<Rebinding>,
+    mut actions: ResMut<ActionState>,
+    mut device_snapshot: ResMut<DeviceSnapshot>,
+) {
+    let mut mouse_motion = Vec2::zero();
+    for event in mouse_motion_reader.iter(&mouse_motion_events) {
+        mouse_motion += event.delta;
+    }
+
+    let devices = Devices {
+        keys: &keys,
+        mouse_buttons: &mouse_buttons,
+        mouse_motion,
+        gamepad: &gamepad,
+    };
+
+    // the key that is being bound should not do anything else
+    actions.update(&bindings, &devices, rebinding.0.is_some());
+    // demos record the devices of every frame
+    *device_snapshot = DeviceSnapshot::capture(&devices);
+}
+
+/// F8 walks through all actions and binds each to the next key or mouse button pressed,
+/// backspace keeps the current binding. The bindings are saved once the last action is done.
+pub fn rebind_actions(
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    mut bindings: ResMut<InputBindings>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    let index = match rebinding.0 {
+        Some(index) => index,
+        None => {
+            if keys.just_pressed(KeyCode::F8) {
+                rebinding.0 = Some(0);
+                println!("Rebinding, press a key for {:?} (backspace keeps {:?})", ACTIONS[0], bindings.actions.get(&ACTIONS[0]));
+            }
+            return;
+        },
+    };
+
+    let action = ACTIONS[index];
+    let pressed_key = BINDABLE_KEYS.iter().find(|key| keys.just_pressed(**key) && **key != KeyCode::Back);
+    let pressed_button = [MouseButton::Left, MouseButton::Right, MouseButton::Middle].iter().find(|button| mouse_buttons.just_pressed(**button)).copied();
+
+    if let Some(key) = pressed_key {
+        bindings.rebind(action, Binding::key(*key));
+    } else if let Some(button) = pressed_button {
+        bindings.rebind(action, Binding::mouse(button));
+    } else if !keys.just_pressed(KeyCode::Back) {
+        return;
+    }
+
+    if index + 1 < ACTIONS.len() {
+        rebinding.0 = Some(index + 1);
+        println!("Press a key for {:?} (backspace keeps {:?})", ACTIONS[index + 1], bindings.actions.get(&ACTIONS[index + 1]));
+    } else {
+        rebinding.0 = None;
+        match bindings.save(BINDINGS_PATH) {
+            Ok(()) => println!("Saved bindings to {}", BINDINGS_PATH),
+            Err(error) => println!("Failed to write bindings to {}: {}", BINDINGS_PATH, error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{input::gamepad::{Gamepad, GamepadAxisType, GamepadButtonType, GamepadEvent, GamepadEventType}, prelude::{Input, KeyCode, MouseButton, Vec2}};
+
+    use super::*;
+
+    struct Setup {
+        keys: Input<KeyCode>,
+        mouse_buttons: Input<MouseButton>,
+        gamepad: GamepadState,
+        bindings: InputBindings,
+        actions: ActionState,
+    }
+
+    impl Setup {
+        fn new() -> Self {
+            Self {
+                keys: Input::default(),
+                mouse_buttons: Input::default(),
+                gamepad: GamepadState::default(),
+                bindings: InputBindings::default(),
+                actions: ActionState::default(),
+            }
+        }
+
+        fn send(&mut self, gamepad: usize, event_type: GamepadEventType) {
+            self.gamepad.handle_event(&GamepadEvent(Gamepad(gamepad), event_type));
+        }
+
+        fn update(&mut self) {
+            let devices = Devices {
+                keys: &self.keys,
+                mouse_buttons: &self.mouse_buttons,
+                mouse_motion: Vec2::zero(),
+                gamepad: &self.gamepad,
+            };
+            self.actions.update(&self.bindings, &devices, false);
+        }
+    }
+
+    #[test]
+    fn test_left_stick_moves_with_radial_deadzone() {
+        let mut setup = Setup::new();
+        setup.send(0, GamepadEventType::Connected);
+
+        setup.send(0, GamepadEventType::AxisChanged(GamepadAxisType::LeftStickY, 0.1));
+        setup.update();
+        assert_eq!(setup.actions.axis(Axis::MoveForward), 0.0);
+
+        setup.send(0, GamepadEventType::AxisChanged(GamepadAxisType::LeftStickY, 0.55));
+        setup.update();
+        assert!((setup.actions.axis(Axis::MoveForward) - 0.5).abs() < 0.001);
+
+        setup.send(0, GamepadEventType::AxisChanged(GamepadAxisType::LeftStickY, 1.0));
+        setup.update();
+        assert!((setup.actions.axis(Axis::MoveForward) - 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_trigger_fires_past_threshold() {
+        let mut setup = Setup::new();
+        setup.send(0, GamepadEventType::Connected);
+
+        setup.send(0, GamepadEventType::ButtonChanged(GamepadButtonType::RightTrigger2, 0.3));
+        setup.update();
+        assert!(!setup.actions.pressed(Action::Fire));
+
+        setup.send(0, GamepadEventType::ButtonChanged(GamepadButtonType::RightTrigger2, 0.8));
+        setup.update();
+        assert!(setup.actions.just_pressed(Action::Fire));
+
+        setup.update();
+        assert!(setup.actions.pressed(Action::Fire));
+        assert!(!setup.actions.just_pressed(Action::Fire));
+    }
+
+    #[test]
+    fn test_unplugging_switches_to_next_gamepad() {
+        let mut setup = Setup::new();
+        setup.send(0, GamepadEventType::Connected);
+        setup.send(1, GamepadEventType::Connected);
+
+        // only the active gamepad drives the player
+        setup.send(1, GamepadEventType::ButtonChanged(GamepadButtonType::South, 1.0));
+        setup.update();
+        assert!(!setup.actions.pressed(Action::Jump));
+
+        setup.send(0, GamepadEventType::AxisChanged(GamepadAxisType::LeftStickY, 1.0));
+        setup.send(0, GamepadEventType::Disconnected);
+        setup.update();
+        assert_eq!(setup.gamepad.active, Some(Gamepad(1)));
+        assert_eq!(setup.actions.axis(Axis::MoveForward), 0.0);
+
+        setup.send(1, GamepadEventType::ButtonChanged(GamepadButtonType::South, 1.0));
+        setup.update();
+        assert!(setup.actions.just_pressed(Action::Jump));
+    }
+
+    #[test]
+    fn test_device_snapshot_replays_the_same_actions() {
+        let mut setup = Setup::new();
+        setup.send(0, GamepadEventType::Connected);
+        setup.send(0, GamepadEventType::ButtonChanged(GamepadButtonType::RightTrigger2, 0.8));
+        setup.send(0, GamepadEventType::AxisChanged(GamepadAxisType::LeftStickY, 0.55));
+        setup.keys.press(KeyCode::Space);
+        setup.mouse_buttons.press(MouseButton::Right);
+
+        let devices = Devices {
+            keys: &setup.keys,
+            mouse_buttons: &setup.mouse_buttons,
+            mouse_motion: Vec2::new(3.0, -2.0),
+            gamepad: &setup.gamepad,
+        };
+        let snapshot = DeviceSnapshot::capture(&devices);
+        let mut live = ActionState::default();
+        live.update(&setup.bindings, &devices, false);
+
+        let mut replayed = ActionState::default();
+        snapshot.replay(|devices| replayed.update(&setup.bindings, devices, false));
+
+        assert_eq!(replayed, live);
+        assert!(replayed.pressed(Action::Fire));
+    }
+
+    #[test]
+    fn test_default_actions_do_not_share_bindings() {
+        let bindings = InputBindings::default();
+        let mut seen: Vec<(&Binding, Action)> = Vec::new();
+
+        for action in ACTIONS {
+            for binding in bindings.actions.get(action).into_iter().flatten() {
+                if let Some((_, other)) = seen.iter().find(|(seen_binding, _)| *seen_binding == binding) {
+                    panic!("{:?} is bound to both {:?} and {:?}", binding, other, action);
+                }
+                seen.push((binding, *action));
+            }
+        }
+    }
+}
@@ -0,0 +1,435 @@
+use std::{error::Error, fs, time::Duration};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    controls::{ActionState, DeviceSnapshot, InputBindings},
+    movement::{PhysicsTick, RigidBody},
+    player::Player,
+    snapshot::{Checksum, PhysicsSnapshot},
+};
+
+pub const DEMO_PATH: &str = "./demo.ron";
+
+/// The input of a single frame
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DemoFrame {
+    /// physics tick the input was applied on, a different tick on playback means the demo desynced
+    pub tick: u64,
+    pub delta_seconds: f32,
+    /// the raw devices, replayed through the bindings of the demo
+    pub devices: DeviceSnapshot,
+}
+
+/// Recorded input that replays deterministically from the snapshot it started at
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Demo {
+    pub start: PhysicsSnapshot,
+    /// the bindings at the start, changing them later does not change the demo
+    pub bindings: InputBindings,
+    /// the actions held before the first frame, presses in the first frame are relative to them
+    pub actions: ActionState,
+    pub frames: Vec<DemoFrame>,
+    /// `state_checksum` after the last frame
+    pub checksum: u64,
+}
+
+impl Demo {
+    pub fn to_ron(&self) -> Result<String, Box<dyn Error>> {
+        Ok(ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?)
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(ron::de::from_str(source)?)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.to_ron()?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        Self::from_ron(&fs::read_to_string(path)?)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlaybackResult {
+    pub expected: u64,
+    pub actual: u64,
+    /// first frame that ran on a different tick than it was recorded on
+    pub desynced_frame: Option<usize>,
+}
+
+impl PlaybackResult {
+    pub fn matches(&self) -> bool {
+        self.expected == self.actual && self.desynced_frame.is_none()
+    }
+}
+
+#[derive(Debug)]
+pub enum DemoMode {
+    Idle,
+    Recording(Demo),
+    /// `actions` are updated from the recorded devices and replace the real ones every frame
+    Playing { demo: Demo, frame: usize, desynced_frame: Option<usize>, actions: ActionState },
+}
+
+impl Default for DemoMode {
+    fn default() -> Self {
+        DemoMode::Idle
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DemoState {
+    pub mode: DemoMode,
+    pub last_playback: Option<PlaybackResult>,
+}
+
+/// Checksum of the player transforms and all rigid bodies. The entities are hashed separately and
+/// added up, so the order the world stores them in does not matter.
+pub fn state_checksum(world: &World) -> u64 {
+    let mut sum = 0u64;
+
+    for (_, transform) in world.query::<(&Player, &Transform)>() {
+        let mut checksum = Checksum::new();
+        checksum.write_array(&transform.translation.into());
+        let rotation: [f32; 4] = transform.rotation.into();
+        for value in rotation.iter() {
+            checksum.write_f32(*value);
+        }
+        sum = sum.wrapping_add(checksum.0);
+    }
+
+    for rb in world.query::<&RigidBody>() {
+        let mut checksum = Checksum::new();
+        checksum.write_f32(rb.mass);
+        checksum.write_f32(rb.cor);
+        checksum.write_array(&rb.force.into());
+        checksum.write_array(&rb.velocity.into());
+        checksum.write_array(&rb.position.into());
+        sum = sum.wrapping_add(checksum.0);
+    }
+
+    sum
+}
+
+pub fn start_recording(world: &World, resources: &mut Resources) {
+    let start = PhysicsSnapshot::capture(world, resources);
+    let bindings = resources.get::<InputBindings>().unwrap().clone();
+    // the actions of this frame are recorded as its first frame
+    let actions = resources.get::<ActionState>().unwrap().previous();
+    resources.get_mut::<DemoState>().unwrap().mode = DemoMode::Recording(Demo {
+        start,
+        bindings,
+        actions,
+        frames: Vec::new(),
+        checksum: 0,
+    });
+}
+
+/// the finished demo, `None` if nothing was being recorded
+pub fn stop_recording(world: &World, resources: &mut Resources) -> Option<Demo> {
+    let mut state = resources.get_mut::<DemoState>().unwrap();
+    match std::mem::take(&mut state.mode) {
+        DemoMode::Recording(mut demo) => {
+            demo.checksum = state_checksum(world);
+            Some(demo)
+        },
+        mode => {
+            state.mode = mode;
+            None
+        },
+    }
+}
+
+/// rolls the simulation back to the start of the demo, its input replaces the devices the next time `record_and_play_demos` runs
+pub fn start_playback(demo: Demo, world: &mut World, resources: &mut Resources) {
+    demo.start.restore(world, resources);
+    let mut state = resources.get_mut::<DemoState>().unwrap();
+    let actions = demo.actions.clone();
+    state.mode = DemoMode::Playing { demo, frame: 0, desynced_frame: None, actions };
+    state.last_playback = None;
+}
+
+/// Replays `demo` without a window or devices, `app` has to contain the simulation systems and
+/// `record_and_play_demos`. Returns `None` if the demo did not finish.
+pub fn replay_headless(demo: Demo, app: &mut App) -> Option<PlaybackResult> {
+    let frames = demo.frames.len();
+    start_playback(demo, &mut app.world, &mut app.resources);
+
+    // one more update to compare the state after the last frame
+    for _ in 0..=frames {
+        app.update();
+    }
+
+    app.resources.get::<DemoState>().unwrap().last_playback
+}
+
+// --- Runs after the actions are updated and before anything reads them ---
+
+/// F6 starts and stops recording to `demo.ron`, F7 plays it back. While playing the recorded
+/// devices and frame time replace the real ones.
+pub fn record_and_play_demos(world: &mut World, resources: &mut Resources) {
+    // headless replays have no keyboard
+    let (toggle_recording, toggle_playback) = match resources.get::<Input<KeyCode>>() {
+        Some(keyboard_input) => (keyboard_input.just_pressed(KeyCode::F6), keyboard_input.just_pressed(KeyCode::F7)),
+        None => (false, false),
+    };
+
+    let (recording, playing) = match resources.get::<DemoState>().unwrap().mode {
+        DemoMode::Idle => (false, false),
+        DemoMode::Recording(_) => (true, false),
+        DemoMode::Playing { .. } => (false, true),
+    };
+
+    // the frame that starts recording or playback is already part of the demo
+    if toggle_recording && recording {
+        if let Some(demo) = stop_recording(world, resources) {
+            match demo.save(DEMO_PATH) {
+                Ok(()) => println!("Saved demo of {} frames to {} (checksum {:x})", demo.frames.len(), DEMO_PATH, demo.checksum),
+                Err(error) => println!("Failed to write demo to {}: {}", DEMO_PATH, error),
+            }
+        }
+        return;
+    } else if toggle_recording && !playing {
+        start_recording(world, resources);
+        println!("Recording demo from tick {}", resources.get::<PhysicsTick>().unwrap().0);
+    } else if toggle_playback && playing {
+        resources.get_mut::<DemoState>().unwrap().mode = DemoMode::Idle;
+        println!("Stopped demo");
+        return;
+    } else if toggle_playback && !recording {
+        match Demo::load(DEMO_PATH) {
+            Ok(demo) => {
+                println!("Playing demo of {} frames", demo.frames.len());
+                start_playback(demo, world, resources);
+            },
+            Err(error) => println!("Failed to read demo from {}: {}", DEMO_PATH, error),
+        }
+    }
+
+    let tick = resources.get::<PhysicsTick>().unwrap().0;
+    let mode = std::mem::take(&mut resources.get_mut::<DemoState>().unwrap().mode);
+
+    let mode = match mode {
+        DemoMode::Idle => DemoMode::Idle,
+        DemoMode::Recording(mut demo) => {
+            let delta_seconds = resources.get::<Time>().unwrap().delta_seconds;
+            let devices = resources.get::<DeviceSnapshot>().unwrap().clone();
+            demo.frames.push(DemoFrame { tick, delta_seconds, devices });
+            DemoMode::Recording(demo)
+        },
+        DemoMode::Playing { demo, frame, mut desynced_frame, mut actions } => match demo.frames.get(frame) {
+            Some(demo_frame) => {
+                if demo_frame.tick != tick && desynced_frame.is_none() {
+                    println!("Demo desynced at frame {}, recorded on tick {} but playing on tick {}", frame, demo_frame.tick, tick);
+                    desynced_frame = Some(frame);
+                }
+
+                {
+                    let mut time = resources.get_mut::<Time>().unwrap();
+                    time.delta_seconds = demo_frame.delta_seconds;
+                    time.delta_seconds_f64 = demo_frame.delta_seconds as f64;
+                    time.delta = Duration::from_secs_f32(demo_frame.delta_seconds);
+                }
+                demo_frame.devices.replay(|devices| actions.update(&demo.bindings, devices, false));
+                *resources.get_mut::<ActionState>().unwrap() = actions.clone();
+
+                DemoMode::Playing { demo, frame: frame + 1, desynced_frame, actions }
+            },
+            None => {
+                let result = PlaybackResult {
+                    expected: demo.checksum,
+                    actual: state_checksum(world),
+                    desynced_frame,
+                };
+                if result.matches() {
+                    println!("Demo finished, checksum {:x} matches", result.actual);
+                } else {
+                    println!("Demo finished, checksum {:x} does not match the recorded {:x}", result.actual, result.expected);
+                }
+                resources.get_mut::<DemoState>().unwrap().last_playback = Some(result);
+                DemoMode::Idle
+            },
+        },
+    };
+
+    resources.get_mut::<DemoState>().unwrap().mode = mode;
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{asset::AssetPlugin, prelude::{App, Handle, Input, KeyCode, Mesh, MinimalPlugins, MouseButton, StandardMaterial, Transform, Vec2, Vec3}};
+
+    use crate::{
+        ball::BallMeshes,
+        camera::ShakeEvent,
+        controls::{ActionState, DeviceSnapshot, Devices, GamepadState, InputBindings},
+        movement::*,
+        physics::{self, primitive::Sphere, test_util},
+        player::{self, Player},
+        profile::MovementProfile,
+        tower::BuildMode,
+        weapon::{self, HitEvent, WeaponDefinitions, Weapons},
+    };
+    use super::*;
+
+    const FRAMES: usize = 90;
+
+    fn create_app() -> App {
+        let mut builder = App::build();
+        builder
+            .add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin)
+            .add_asset::<MovementProfile>()
+            .add_asset::<Mesh>()
+            .add_asset::<StandardMaterial>()
+            .add_event::<ShakeEvent>()
+            .add_event::<HitEvent>()
+            .add_resource(physics::create_world_from_triangles(test_util::floor(50.0)))
+            .add_resource(PhysicsTick::default())
+            .add_resource(ActionState::default())
+            .add_resource(InputBindings::default())
+            .add_resource(DeviceSnapshot::default())
+            .add_resource(DemoState::default())
+            .add_resource(BallMeshes::default())
+            .add_resource(BuildMode::default())
+            .add_system(record_and_play_demos.thread_local_system())
+            .add_system(player::update_look_direction.system())
+            .add_system(weapon::fire_weapons.system())
+            .add_system(player::move_player.system())
+            .add_system(apply_gravity.system())
+            .add_system(update_velocity.system())
+            .add_system(resolve_collisions.system())
+            .add_system(update_rigid_bodies.system())
+            .add_system(update_rigid_body_transforms.system())
+            .add_system(update_crouch.system())
+            .add_system(update_stamina.system())
+            .add_system(update_character_velocity.system())
+            .add_system(move_kinematic_entities.system())
+            .add_system(advance_physics_tick.system());
+
+        let mut app = builder.app;
+        let profile = MovementProfile::default();
+
+        // the profile handle never loads, so the player uses the default profile
+        app.world.spawn((
+            Player::new(0.0, 0.0),
+            Transform::from_translation(Vec3::new(0.0, 1.0, 0.0)),
+            Movement(Vec3::zero()),
+            profile.movement.clone(),
+            GroundedState::default(),
+            MovementMode::Walking,
+            CharacterInput::default(),
+            CharacterVelocity::default(),
+            JumpState::default(),
+            CrouchState::default(),
+            Stamina::new(profile.movement.max_stamina),
+            Kinematic,
+            Handle::<MovementProfile>::default(),
+            Weapons::new(&WeaponDefinitions::default()),
+        ));
+
+        let position = Vec3::new(2.0, 3.0, 0.0);
+        app.world.spawn((
+            RigidBody {
+                mass: 1.0,
+                cor: 0.5,
+                force: Vec3::zero(),
+                velocity: Vec3::new(1.0, 0.0, 0.5),
+                position,
+            },
+            Collider {
+                sphere: Sphere::new(Vec3::zero(), 0.2),
+            },
+            Gravity(Vec3::new(0.0, -10.0, 0.0)),
+            Transform::from_translation(position),
+        ));
+
+        app
+    }
+
+    /// walks forward while turning and jumps once, going through the bindings like real input. With `fire` it
+    /// also pulls the trigger every ten frames.
+    fn record(app: &mut App, fire: bool) -> Demo {
+        let mut keys = Input::<KeyCode>::default();
+        let mut mouse_buttons = Input::<MouseButton>::default();
+        let gamepad = GamepadState::default();
+        let bindings = InputBindings::default();
+
+        start_recording(&app.world, &mut app.resources);
+        for frame in 0..FRAMES {
+            keys.update();
+            keys.press(KeyCode::W);
+            if frame == 20 {
+                keys.press(KeyCode::Space);
+            } else {
+                keys.release(KeyCode::Space);
+            }
+
+            mouse_buttons.update();
+            if fire && frame % 10 == 0 {
+                mouse_buttons.press(MouseButton::Left);
+            } else {
+                mouse_buttons.release(MouseButton::Left);
+            }
+
+            let devices = Devices {
+                keys: &keys,
+                mouse_buttons: &mouse_buttons,
+                mouse_motion: if frame < 40 { Vec2::new(30.0, -5.0) } else { Vec2::zero() },
+                gamepad: &gamepad,
+            };
+            app.resources.get_mut::<ActionState>().unwrap().update(&bindings, &devices, false);
+            *app.resources.get_mut::<DeviceSnapshot>().unwrap() = DeviceSnapshot::capture(&devices);
+            app.update();
+        }
+
+        stop_recording(&app.world, &mut app.resources).unwrap()
+    }
+
+    #[test]
+    fn test_replay_matches_recording() {
+        let mut app = create_app();
+        let before = state_checksum(&app.world);
+        let demo = record(&mut app, false);
+        assert_eq!(demo.frames.len(), FRAMES);
+        assert_ne!(demo.checksum, before);
+
+        let demo = Demo::from_ron(&demo.to_ron().unwrap()).unwrap();
+        let result = replay_headless(demo, &mut app).unwrap();
+
+        assert!(result.matches(), "{:?}", result);
+    }
+
+    #[test]
+    fn test_replay_restores_weapons() {
+        let mut app = create_app();
+        let demo = record(&mut app, true);
+        let recorded = PhysicsSnapshot::capture(&app.world, &app.resources);
+
+        let (_, weapons) = app.world.query::<(&Player, &Weapons)>().next().unwrap();
+        let slot = weapons.current().unwrap();
+        assert!(slot.ammo < slot.definition.magazine_size);
+
+        let result = replay_headless(demo, &mut app).unwrap();
+        let replayed = PhysicsSnapshot::capture(&app.world, &app.resources);
+
+        assert!(result.matches(), "{:?}", result);
+        assert_eq!(replayed.checksum(), recorded.checksum());
+    }
+
+    #[test]
+    fn test_replay_with_different_input_does_not_match() {
+        let mut app = create_app();
+        let mut demo = record(&mut app, false);
+        demo.frames[10].devices = DeviceSnapshot::default();
+
+        let result = replay_headless(demo, &mut app).unwrap();
+
+        assert!(!result.matches());
+        assert_eq!(result.desynced_frame, None);
+    }
+}
@@ -1,19 +1,29 @@
-use bevy::{diagnostic::FrameTimeDiagnosticsPlugin, input::{ElementState, mouse::{MouseButtonInput, MouseMotion}}, prelude::*, render::camera::Camera, window::WindowMode};
+use bevy::{diagnostic::FrameTimeDiagnosticsPlugin, prelude::*, window::WindowMode};
 use player::Player;
+use profile::MovementProfile;
 use util::draw_primitives::*;
 
 mod lifetime;
+mod ball;
 mod player;
 mod physics;
 mod math;
 mod game_state;
 mod util;
 mod movement;
+mod snapshot;
+mod profile;
+mod controls;
+mod demo;
+mod camera;
+mod weapon;
+mod tower;
 
-struct MainCamera;
+const COLLISION_MESH_PATH: &str = "./assets/physics/test.glb";
+const PET_PROFILE_PATH: &str = "profiles/pet.ron";
 
 fn main() {
-    let world = physics::create_bvh_from_gltf("./assets/physics/test.glb");
+    let (world, volumes) = physics::load_level(COLLISION_MESH_PATH);
 
     App::build()
         .add_resource(WindowDescriptor {
@@ -29,25 +39,90 @@ fn main() {
         })
         .add_resource(Msaa { samples: 4 })
         .add_resource(world)
+        .add_resource(volumes)
+        .add_resource(movement::PhysicsTick::default())
+        .add_resource(snapshot::QuickSnapshot::default())
+        .add_resource(physics::debug::PhysicsDebug::default())
+        .add_resource(controls::InputBindings::load_or_default(controls::BINDINGS_PATH))
+        .add_resource(controls::ActionState::default())
+        .add_resource(controls::DeviceSnapshot::default())
+        .add_resource(controls::Rebinding::default())
+        .add_resource(controls::GamepadState::default())
+        .add_resource(demo::DemoState::default())
+        .add_resource(weapon::WeaponDefinitions::load_or_default(weapon::WEAPONS_PATH))
+        .add_resource(ball::BallMeshes::default())
+        .add_resource(tower::BuildMode::default())
+        .add_resource(tower::EnemyPath::default())
+        .add_resource(physics::CollisionReload::watch(COLLISION_MESH_PATH))
+        .add_event::<physics::CollisionWorldReloaded>()
+        .add_event::<movement::WaterEvent>()
+        .add_event::<camera::ShakeEvent>()
+        .add_event::<weapon::HitEvent>()
+        .add_event::<weapon::ExplosionEvent>()
         .add_plugins(DefaultPlugins)
         .add_plugin(FrameTimeDiagnosticsPlugin::default())
+        .add_asset::<MovementProfile>()
+        .init_asset_loader::<profile::MovementProfileLoader>()
         .add_startup_system(setup.system())
         .add_startup_system(setup_primitives.system())
         .add_startup_system(player::spawn_player.system())
+        .add_startup_system(spawn_platforms.system())
+        .add_startup_system(spawn_water.system())
+        .add_startup_system(spawn_ladders.system())
+        .add_startup_system(spawn_props.system())
+        .add_startup_system(spawn_pet.system())
+        .add_startup_system(spawn_build_zones.system())
+        .add_startup_system(tower::spawn_tower_ghost.system())
+        .add_system(controls::track_gamepads.system())
+        .add_system(controls::update_action_state.system())
+        .add_system(controls::rebind_actions.system())
+        .add_system(demo::record_and_play_demos.thread_local_system())
+        .add_system(camera::control_camera_rig.system())
+        .add_system(weapon::grab_bodies.system())
         .add_system(crate::lifetime::reduce_lifetime.system())
-        .add_system(update_look_direction.system())
+        .add_system(profile::apply_movement_profiles.system())
+        .add_system(size_pet.system())
+        .add_system(player::update_look_direction.system())
         .add_system(player::move_player.system())
+        .add_system(tower::build_towers.system())
+        .add_system(tower::update_tower_ghost.system())
+        .add_system(weapon::fire_weapons.system())
+        .add_system(weapon::draw_hits.system())
         .add_system(player::shake_when_hit_ground.system())
         .add_system(debug_player.system())
+        .add_system(crate::physics::reload_collision_world.system())
+        .add_system(spawn_marked_volumes.system())
+        .add_system(crate::movement::push_out_of_geometry.system())
+        .add_system(crate::movement::animate_platforms.system())
+        .add_system(crate::physics::update_collision_instances.system())
+        .add_system(crate::movement::carry_riders.system())
+        .add_system(player::turn_with_ground.system())
+        .add_system(crate::movement::detect_water.system())
         .add_system(crate::movement::apply_gravity.system())
+        .add_system(crate::movement::apply_buoyancy.system())
+        .add_system(weapon::hold_bodies.system())
         .add_system(crate::movement::update_velocity.system())
         .add_system(crate::movement::resolve_collisions.system())
         .add_system(crate::movement::update_rigid_bodies.system())
+        .add_system(weapon::update_projectiles.system())
+        .add_system(weapon::explode.system())
         .add_system(crate::movement::update_rigid_body_transforms.system())
+        .add_system(ball::add_ball_meshes.system())
+        .add_system(weapon::rotate_held_bodies.system())
+        .add_system(crate::movement::update_movement_mode.system())
+        .add_system(crate::movement::update_crouch.system())
+        .add_system(crate::movement::update_stamina.system())
+        .add_system(crate::movement::update_character_velocity.system())
         .add_system(crate::movement::move_kinematic_entities.system())
-        .add_system(update_camera.system())
+        .add_system(crate::movement::advance_physics_tick.system())
+        .add_system(crate::snapshot::quick_save_and_restore.thread_local_system())
+        .add_system(player::update_camera_height.system())
+        .add_system(camera::shake_cameras.system())
+        .add_system(camera::update_camera_effects.system())
+        .add_system(camera::update_camera.system())
         .add_system(game_state::toggle_cursor_and_exit.system())
-        .add_system(player::update_trauma.system())
+        .add_system(crate::physics::debug::toggle_physics_debug.system())
+        .add_system(crate::physics::debug::draw_physics_debug.system())
         .add_system(util::draw_primitives::update_primitives.system())
         .add_system(crate::lifetime::remove_entities_based_on_lifetime.system())
         .run();
@@ -57,6 +132,9 @@ fn setup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
 ) {
+    // the collision mesh is reloaded by `physics::CollisionReload`, this takes care of the rendered scene
+    asset_server.watch_for_changes().unwrap();
+
     commands
         .spawn_scene(asset_server.load("physics/test.glb"))
         .spawn(LightComponents {
@@ -68,49 +146,213 @@ fn setup(
                 .looking_at(Vec3::default(), Vec3::unit_y()),
             ..Default::default()
         })
-        .with(MainCamera);
+        .with(camera::MainCamera)
+        .with(camera::CameraEffects::default())
+        .with(camera::CameraShake::default())
+        .with(camera::CameraRig::default());
 }
 
-#[derive(Default)]
-struct State {
-    mouse_button_event_reader: EventReader<MouseButtonInput>,
-    mouse_motion_event_reader: EventReader<MouseMotion>,
+fn spawn_platforms(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let size = Vec3::new(4.0, 0.5, 4.0);
+    let collision_mesh = std::sync::Arc::new(physics::create_box_mesh(size * -0.5, size * 0.5));
+
+    let platforms = [
+        // elevator
+        movement::MovingPlatform {
+            origin: Vec3::new(6.0, 0.0, 6.0),
+            offset: Vec3::new(0.0, 6.0, 0.0),
+            period: 8.0,
+            ..Default::default()
+        },
+        // carousel
+        movement::MovingPlatform {
+            origin: Vec3::new(-8.0, 1.0, -6.0),
+            angular_speed: 0.5,
+            ..Default::default()
+        },
+    ];
+
+    for platform in platforms.iter() {
+        commands
+            .spawn(PbrComponents {
+                mesh: meshes.add(Mesh::from(shape::Box::new(size.x(), size.y(), size.z()))),
+                material: materials.add(Color::rgb(0.3, 0.5, 0.3).into()),
+                transform: Transform::from_translation(platform.origin),
+                ..Default::default()
+            })
+            .with(platform.clone())
+            .with(physics::CollisionInstance::new(collision_mesh.clone()));
+    }
 }
 
-fn update_look_direction(
-    mut state: Local<State>,
-    time: Res<Time>,
-    mouse_button_events: Res<Events<MouseButtonInput>>,
-    mouse_motion_events: Res<Events<MouseMotion>>,
-    mut player_query: Query<&mut Player>,
+/// water, ladders and build zones marked in the level, spawned again whenever the level is reloaded
+fn spawn_marked_volumes(
+    mut commands: Commands,
+    mut spawned: Local<bool>,
+    mut reload_reader: Local<EventReader<physics::CollisionWorldReloaded>>,
+    reload_events: Res<Events<physics::CollisionWorldReloaded>>,
+    volumes: Res<physics::LevelVolumes>,
+    marked_query: Query<(Entity, &physics::MarkedVolume)>,
 ) {
-    let mut mouse_delta = Vec2::default();
-    for event in state.mouse_motion_event_reader.iter(&mouse_motion_events) {
-        mouse_delta += event.delta * time.delta_seconds * 0.1;
+    let reloaded = reload_reader.iter(&reload_events).next().is_some();
+    if *spawned && !reloaded {
+        return;
     }
+    *spawned = true;
 
-    let mut pressed = false;
-    for event in state.mouse_button_event_reader.iter(&mouse_button_events) {
-        if event.button == MouseButton::Left && event.state == ElementState::Pressed {
-            pressed = true;
+    for (entity, _) in marked_query.iter() {
+        commands.despawn(entity);
+    }
+
+    // the level renders its own meshes for them
+    for volume in volumes.0.iter() {
+        if volume.is("water") {
+            commands.spawn((movement::WaterVolume::new(volume.min, volume.max), volume.clone()));
+        } else if volume.is("ladder") {
+            commands.spawn((movement::Ladder::new(volume.min, volume.max), volume.clone()));
+        } else if volume.is("build_zone") {
+            commands.spawn((tower::BuildZone::new(volume.min, volume.max), volume.clone()));
         }
     }
+}
+
+/// a test pond
+fn spawn_water(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let (min, max) = (Vec3::new(-16.0, -2.0, 6.0), Vec3::new(-10.0, 0.8, 12.0));
+    let size = max - min;
+    commands
+        .spawn(PbrComponents {
+            mesh: meshes.add(Mesh::from(shape::Box::new(size.x(), size.y(), size.z()))),
+            material: materials.add(Color::rgba(0.1, 0.3, 0.7, 0.5).into()),
+            transform: Transform::from_translation((min + max) * 0.5),
+            draw: Draw {
+                is_transparent: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with(movement::WaterVolume::new(min, max));
+}
+
+/// a test wall with a ladder up to its top
+fn spawn_ladders(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let wall_size = Vec3::new(3.0, 4.0, 2.0);
+    let wall_position = Vec3::new(10.0, wall_size.y() * 0.5, -6.0);
+    commands
+        .spawn(PbrComponents {
+            mesh: meshes.add(Mesh::from(shape::Box::new(wall_size.x(), wall_size.y(), wall_size.z()))),
+            material: materials.add(Color::rgb(0.5, 0.4, 0.3).into()),
+            transform: Transform::from_translation(wall_position),
+            ..Default::default()
+        })
+        .with(physics::CollisionInstance::new(std::sync::Arc::new(physics::create_box_mesh(wall_size * -0.5, wall_size * 0.5))));
+
+    // on the +z face of the wall, not part of the collision
+    let min = wall_position + Vec3::new(-0.5, -wall_size.y() * 0.5, wall_size.z() * 0.5);
+    let max = min + Vec3::new(1.0, wall_size.y(), 0.2);
+    let size = max - min;
+    commands
+        .spawn(PbrComponents {
+            mesh: meshes.add(Mesh::from(shape::Box::new(size.x(), size.y(), size.z()))),
+            material: materials.add(Color::rgb(0.6, 0.3, 0.1).into()),
+            transform: Transform::from_translation((min + max) * 0.5),
+            ..Default::default()
+        })
+        .with(movement::Ladder::new(min, max));
+}
 
-    for mut player in player_query.iter_mut() {
-        player.yaw += mouse_delta.x();
-        player.pitch += mouse_delta.y();
+/// a test build zone next to the test enemy path
+fn spawn_build_zones(mut commands: Commands) {
+    commands.spawn((tower::BuildZone::new(Vec3::new(-6.0, -1.0, -14.0), Vec3::new(6.0, 3.0, -8.0)),));
+}
 
-        player.pitch = player.pitch.min((0.5 * std::f32::consts::PI) - 0.01);
-        player.pitch = player.pitch.max(-((0.5 * std::f32::consts::PI) - 0.01));
+/// balls to pick up and throw around, the last one is too heavy to carry
+fn spawn_props(
+    mut commands: Commands,
+    mut ball_meshes: ResMut<ball::BallMeshes>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let props = [
+        (Vec3::new(-1.0, 3.0, 2.0), 0.2, 1.0),
+        (Vec3::new(0.0, 3.0, 2.0), 0.3, 4.0),
+        (Vec3::new(1.0, 3.0, 2.0), 0.4, 12.0),
+        (Vec3::new(2.5, 3.0, 2.0), 0.6, 40.0),
+    ];
 
-        player.action = pressed;
+    for (position, radius, mass) in props.iter() {
+        let (position, radius, mass) = (*position, *radius, *mass);
+        let handles = ball_meshes.get(&mut meshes, &mut materials, radius, ball::BALL_COLOR);
+        let body = movement::RigidBody {
+            force: Vec3::zero(),
+            mass,
+            cor: 0.5,
+            position,
+            velocity: Vec3::zero(),
+        };
+        ball::spawn_ball(&mut commands, handles, body, radius, 1.0);
     }
 }
 
-fn debug_player(
+fn spawn_pet(
     mut commands: Commands,
+    asset_server: Res<AssetServer>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let movement = MovementProfile::default().movement;
+
+    // walks like the player but has no input of its own, the movement data is replaced by its profile once it is loaded
+    commands
+        .spawn(PbrComponents {
+            // a unit sphere, `size_pet` scales it to the collision radius
+            mesh: meshes.add(Mesh::from(shape::Icosphere { radius: 1.0, subdivisions: 3 })),
+            material: materials.add(Color::rgb(0.8, 0.6, 0.3).into()),
+            transform: Transform {
+                translation: Vec3::new(-2.0, 5.0, 3.0),
+                scale: Vec3::splat(movement.radius),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with(movement::Movement(Vec3::zero()))
+        .with(movement::GroundedState::default())
+        .with(movement::MovementMode::Walking)
+        .with(movement::CharacterInput::default())
+        .with(movement::CharacterVelocity::default())
+        .with(movement::JumpState::default())
+        .with(movement::CrouchState::default())
+        .with(movement::Stamina::new(movement.max_stamina))
+        .with(movement::WaterState::default())
+        .with(movement::LadderState::default())
+        .with(movement::Kinematic)
+        .with(movement)
+        .with(asset_server.load::<MovementProfile, _>(PET_PROFILE_PATH))
+        .with(camera::SpectatorTarget::Pet);
+}
+
+/// keeps the pet mesh as large as its collider, the radius changes when the profile is loaded or edited
+fn size_pet(mut pets: Query<(&camera::SpectatorTarget, &movement::MovementData, &mut Transform)>) {
+    for (target, movement, mut transform) in pets.iter_mut() {
+        if *target == camera::SpectatorTarget::Pet {
+            transform.scale = Vec3::splat(movement.radius);
+        }
+    }
+}
+
+fn debug_player(
     world: Res<crate::physics::World>,
     player_query: Query<(&Player, &Transform)>,
 ) {
@@ -122,46 +364,5 @@ fn debug_player(
         if let Some(intersection) = world.raycast(&ray) {
             crate::util::draw_primitives::draw_line_for((intersection.position, intersection.position + intersection.normal), 1);
         }
-
-        // DEBUG spawn sphere
-        let radius = 0.2;
-        let pos = pos + (look * 2.0);
-        if player.action {
-            commands.spawn(PbrComponents {
-                mesh: meshes.add(Mesh::from(shape::Icosphere { radius, subdivisions: 3, })),
-                material: materials.add(Color::rgb(0.5, 0.5, 0.5).into()),
-                transform: Transform::from_translation(pos),
-                ..Default::default()
-            })
-            .with(crate::movement::RigidBody {
-                force: Vec3::zero(),
-                mass: 1.0,
-                cor: 0.5,
-                position: pos,
-                velocity: Vec3::zero(),
-            })
-            .with(crate::movement::Collider {
-                sphere: crate::physics::primitive::Sphere {
-                    center: Vec3::zero(),
-                    radius,
-                }
-            })
-            .with(crate::movement::Gravity(Vec3::new(0.0, -10.0, 0.0)))
-            .with(crate::lifetime::Lifetime(60 * 20 * 1000));
-        }
-    }
-}
-
-fn update_camera(
-    player_query: Query<(&Player, &Transform)>,
-    mut camera_query: Query<(&MainCamera, &Camera, &mut Transform)>,
-) {
-    for (player, player_transform) in player_query.iter() {
-        let direction = player.get_look_direction();
-
-        for (_, _, mut transform) in camera_query.iter_mut() {
-            let camera_position = player_transform.translation + (Vec3::unit_y() * player.camera_height);
-            *transform = Transform::from_translation(camera_position).looking_at(camera_position + direction, Vec3::unit_y());
-        }
     }
 }
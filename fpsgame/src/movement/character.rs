@@ -0,0 +1,289 @@
+use bevy::prelude::*;
+
+use crate::physics::primitive::Sphere;
+use super::{GroundedState, LadderState, Movement, MovementData, MovementMode, WaterState, move_entities::FIXED_UPDATE, update_climb_velocity, update_swim_velocity};
+
+const HEADROOM_SKIN: f32 = 0.01; // touching the ground does not count as being blocked
+const STOP_SPEED: f32 = 1.0; // friction stops slower movement as if it was moving this fast
+
+/// Velocity of a kinematic entity, added to its `Movement` every physics tick
+#[derive(Debug, Default, Clone)]
+pub struct CharacterVelocity(pub Vec3);
+
+/// How fast the entity can move while carrying something, 1 is full speed
+#[derive(Debug, Clone)]
+pub struct Encumbrance(pub f32);
+
+impl Default for Encumbrance {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// What the entity wants to do this frame, filled in by the player or an AI
+#[derive(Debug, Default, Clone)]
+pub struct CharacterInput {
+    /// horizontal direction to move in with a length of up to 1, may point up and down while swimming
+    pub wish_direction: Vec3,
+    /// where the entity is looking, ladders are only grabbed when facing them
+    pub look_direction: Vec3,
+    /// jump was pressed this frame
+    pub jump_pressed: bool,
+    /// jump is held down, releasing it early cuts the jump short
+    pub jump_held: bool,
+    pub crouch: bool,
+    pub sprint: bool,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct JumpState {
+    /// frames since jump was pressed while it could not be performed yet
+    pub buffered_frames: Option<u32>,
+    pub is_jumping: bool,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct CrouchState {
+    pub is_crouching: bool,
+    /// radius to return to when standing up
+    pub standing_radius: f32,
+    /// how far the center was moved this frame to keep the feet or the head in place,
+    /// lets the camera smooth out the jump
+    pub center_shift: f32,
+}
+
+/// Stamina pool that is used up by sprinting, read by the HUD
+#[derive(Debug, Default, Clone)]
+pub struct Stamina {
+    pub current: f32,
+    pub max: f32,
+    /// seconds left until stamina starts to regenerate
+    pub regen_delay: f32,
+    pub is_sprinting: bool,
+    /// ran out of stamina, sprint has to be released before sprinting again
+    pub exhausted: bool,
+}
+
+impl Stamina {
+    pub fn new(max: f32) -> Self {
+        Self {
+            current: max,
+            max,
+            ..Default::default()
+        }
+    }
+
+    /// how full the pool is from 0 to 1
+    pub fn fraction(&self) -> f32 {
+        if self.max > 0.0 { (self.current / self.max).max(0.0).min(1.0) } else { 0.0 }
+    }
+}
+
+// --- Runs before the kinematic entities are moved ---
+
+/// shrinks the collision sphere while crouch is held and grows it back once there is room
+pub fn update_crouch(
+    world: Res<crate::physics::World>,
+    mut entities: Query<(&mut MovementData, &CharacterInput, &GroundedState, &mut CrouchState, &mut Transform)>,
+) {
+    for (mut movement_data, input, grounded_state, mut crouch_state, mut transform) in entities.iter_mut() {
+        crouch_state.center_shift = 0.0;
+
+        if input.crouch && !crouch_state.is_crouching {
+            let difference = movement_data.radius - movement_data.crouch_radius;
+
+            // the feet stay on the ground, in the air they are pulled up to reach higher ledges
+            let shift = if grounded_state.is_grounded { -difference } else { difference };
+
+            crouch_state.is_crouching = true;
+            crouch_state.standing_radius = movement_data.radius;
+            crouch_state.center_shift = shift;
+            movement_data.radius = movement_data.crouch_radius;
+            transform.translation += Vec3::unit_y() * shift;
+        } else if !input.crouch && crouch_state.is_crouching {
+            let difference = crouch_state.standing_radius - movement_data.radius;
+
+            // grow the way we shrunk, if that is blocked try the other direction
+            let preferred = if grounded_state.is_grounded { difference } else { -difference };
+            let fits = |shift: f32| {
+                let standing = Sphere::new(transform.translation + Vec3::unit_y() * shift, crouch_state.standing_radius - HEADROOM_SKIN);
+                world.collide_sphere(&standing).is_none()
+            };
+
+            let shift = if fits(preferred) {
+                Some(preferred)
+            } else if fits(-preferred) {
+                Some(-preferred)
+            } else {
+                None
+            };
+
+            if let Some(shift) = shift {
+                crouch_state.is_crouching = false;
+                crouch_state.center_shift = shift;
+                movement_data.radius = crouch_state.standing_radius;
+                transform.translation += Vec3::unit_y() * shift;
+            }
+        }
+    }
+}
+
+/// sprinting drains stamina, it is only possible while walking on the ground
+pub fn update_stamina(
+    mut entities: Query<(&MovementData, &GroundedState, &MovementMode, &CharacterInput, Option<&CrouchState>, &mut Stamina)>,
+) {
+    for (movement_data, grounded_state, mode, input, crouch_state, mut stamina) in entities.iter_mut() {
+        let is_crouching = crouch_state.map(|crouch_state| crouch_state.is_crouching).unwrap_or(false);
+
+        // the profile may have changed the size of the pool
+        stamina.max = movement_data.max_stamina;
+        stamina.current = stamina.current.min(stamina.max);
+        if !input.sprint {
+            stamina.exhausted = false;
+        }
+
+        stamina.is_sprinting = input.sprint
+            && !stamina.exhausted
+            && stamina.current > 0.0
+            && *mode == MovementMode::Walking
+            && grounded_state.is_grounded
+            && !is_crouching
+            && input.wish_direction.length_squared() > std::f32::EPSILON;
+
+        if stamina.is_sprinting {
+            stamina.current -= movement_data.stamina_drain * FIXED_UPDATE;
+            stamina.regen_delay = movement_data.stamina_regen_delay;
+            if stamina.current <= 0.0 {
+                stamina.current = 0.0;
+                stamina.exhausted = true;
+            }
+        } else if stamina.regen_delay > 0.0 {
+            stamina.regen_delay = (stamina.regen_delay - FIXED_UPDATE).max(0.0);
+        } else {
+            stamina.current = (stamina.current + movement_data.stamina_regen * FIXED_UPDATE).min(stamina.max);
+        }
+    }
+}
+
+pub fn update_character_velocity(
+    mut entities: Query<(&MovementData, &GroundedState, &MovementMode, &CharacterInput, Option<&CrouchState>, Option<&Stamina>, Option<&WaterState>, Option<&LadderState>, Option<&Encumbrance>, &mut JumpState, &mut CharacterVelocity, &mut Movement)>,
+) {
+    for (movement_data, grounded_state, mode, input, crouch_state, stamina, water_state, ladder_state, encumbrance, mut jump_state, mut velocity, mut movement) in entities.iter_mut() {
+        if *mode == MovementMode::Flying {
+            velocity.0 = Vec3::zero();
+            *jump_state = JumpState::default();
+            continue;
+        }
+
+        if let (MovementMode::Swimming, Some(water_state)) = (*mode, water_state) {
+            update_swim_velocity(movement_data, water_state, input, &mut velocity);
+            movement.0 += velocity.0 * FIXED_UPDATE;
+            continue;
+        }
+
+        if let (MovementMode::Climbing, Some(ladder_state)) = (*mode, ladder_state) {
+            update_climb_velocity(movement_data, ladder_state, input, &mut velocity);
+            movement.0 += velocity.0 * FIXED_UPDATE;
+            continue;
+        }
+
+        let mut wish_speed = movement_data.max_speed * input.wish_direction.length().min(1.0);
+        if crouch_state.map(|crouch_state| crouch_state.is_crouching).unwrap_or(false) {
+            wish_speed *= movement_data.crouch_speed_factor;
+        }
+        if stamina.map(|stamina| stamina.is_sprinting).unwrap_or(false) {
+            wish_speed *= movement_data.sprint_speed_factor;
+        }
+        if let Some(encumbrance) = encumbrance {
+            wish_speed *= encumbrance.0;
+        }
+
+        let grounded = grounded_state.is_grounded && velocity.0.y() <= 0.0;
+        update_horizontal_velocity(movement_data, grounded, input.wish_direction, wish_speed, &mut velocity);
+        update_jump(movement_data, grounded_state, input, &mut jump_state, &mut velocity);
+        movement.0 += velocity.0 * FIXED_UPDATE;
+    }
+}
+
+/// friction and acceleration on the ground, weaker acceleration and steering in the air
+pub fn update_horizontal_velocity(movement_data: &MovementData, grounded: bool, wish_direction: Vec3, wish_speed: f32, velocity: &mut CharacterVelocity) {
+    let mut horizontal = Vec3::new(velocity.0.x(), 0.0, velocity.0.z());
+    let wish_direction = Vec3::new(wish_direction.x(), 0.0, wish_direction.z());
+    let wish_direction = if wish_direction.length_squared() > std::f32::EPSILON {
+        wish_direction.normalize()
+    } else {
+        Vec3::zero()
+    };
+
+    if grounded {
+        let speed = horizontal.length();
+        if speed > std::f32::EPSILON {
+            let drop = speed.max(STOP_SPEED) * movement_data.ground_friction * FIXED_UPDATE;
+            horizontal *= (speed - drop).max(0.0) / speed;
+        }
+
+        horizontal = accelerate(horizontal, wish_direction, wish_speed, movement_data.ground_acceleration);
+    } else {
+        horizontal = accelerate(horizontal, wish_direction, wish_speed, movement_data.air_acceleration);
+
+        // air control turns the velocity towards the wish direction without gaining speed
+        let speed = horizontal.length();
+        if speed > std::f32::EPSILON && wish_direction != Vec3::zero() {
+            let direction = horizontal / speed;
+            let turn = (movement_data.air_control * FIXED_UPDATE).min(1.0);
+            let turned = direction + (wish_direction - direction) * turn;
+            if turned.length_squared() > std::f32::EPSILON {
+                horizontal = turned.normalize() * speed;
+            }
+        }
+    }
+
+    velocity.0 = Vec3::new(horizontal.x(), velocity.0.y(), horizontal.z());
+}
+
+/// adds speed in `wish_direction` until `wish_speed` is reached, speed in other directions is kept
+fn accelerate(velocity: Vec3, wish_direction: Vec3, wish_speed: f32, acceleration: f32) -> Vec3 {
+    let current_speed = velocity.dot(wish_direction);
+    let add_speed = wish_speed - current_speed;
+    if add_speed <= 0.0 {
+        return velocity;
+    }
+
+    velocity + wish_direction * add_speed.min(acceleration * wish_speed * FIXED_UPDATE)
+}
+
+/// gravity, jumping, coyote time, jump buffering and cutting the jump short
+pub fn update_jump(movement_data: &MovementData, grounded_state: &GroundedState, input: &CharacterInput, jump_state: &mut JumpState, velocity: &mut CharacterVelocity) {
+    // still being grounded right after taking off does not count as landing
+    let grounded = grounded_state.is_grounded && velocity.0.y() <= 0.0;
+    if grounded {
+        velocity.0.set_y(0.0);
+        jump_state.is_jumping = false;
+    }
+
+    if input.jump_pressed {
+        jump_state.buffered_frames = Some(0);
+    }
+
+    // coyote time, jumping is still possible for a few frames after walking off a ledge
+    let can_jump = !jump_state.is_jumping && grounded_state.frames_since_grounded <= movement_data.coyote_frames;
+
+    jump_state.buffered_frames = match jump_state.buffered_frames {
+        Some(_) if can_jump => {
+            velocity.0.set_y(movement_data.jump_speed);
+            jump_state.is_jumping = true;
+            None
+        },
+        Some(frames) if frames < movement_data.jump_buffer_frames => Some(frames + 1),
+        _ => None,
+    };
+
+    // releasing jump early gives a lower jump
+    if jump_state.is_jumping && !input.jump_held && velocity.0.y() > 0.0 {
+        velocity.0.set_y(velocity.0.y().min(movement_data.jump_speed * movement_data.jump_cut));
+    }
+
+    if !grounded || jump_state.is_jumping {
+        velocity.0 -= Vec3::unit_y() * movement_data.gravity * FIXED_UPDATE;
+    }
+}
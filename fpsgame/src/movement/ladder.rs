@@ -0,0 +1,53 @@
+use bevy::prelude::*;
+
+use super::{CharacterInput, CharacterVelocity, MovementData};
+
+/// Box in front of something climbable, entities facing it while touching it start climbing
+#[derive(Debug, Clone, Copy)]
+pub struct Ladder {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+/// The ladder an entity is climbing
+#[derive(Debug, Default, Clone)]
+pub struct LadderState {
+    pub ladder: Option<Entity>,
+    /// points away from the ladder towards the climber
+    pub normal: Vec3,
+    /// frames left until a ladder can be grabbed again after jumping off
+    pub regrab_frames: u32,
+}
+
+impl Ladder {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn top(&self) -> f32 {
+        self.max.y()
+    }
+
+    pub fn touches(&self, center: Vec3, radius: f32) -> bool {
+        let closest = center.max(self.min).min(self.max);
+        (closest - center).length_squared() <= radius * radius
+    }
+
+    /// the ladder is climbed from the side of its thinner horizontal axis the entity is on
+    pub fn normal_towards(&self, point: Vec3) -> Vec3 {
+        let size = self.max - self.min;
+        let offset = point - (self.min + self.max) * 0.5;
+
+        if size.x() < size.z() {
+            Vec3::unit_x() * offset.x().signum()
+        } else {
+            Vec3::unit_z() * offset.z().signum()
+        }
+    }
+}
+
+/// forward and back move up and down the ladder, nothing else
+pub fn update_climb_velocity(movement_data: &MovementData, ladder_state: &LadderState, input: &CharacterInput, velocity: &mut CharacterVelocity) {
+    let forward = input.wish_direction.dot(-ladder_state.normal).max(-1.0).min(1.0);
+    velocity.0 = Vec3::unit_y() * forward * movement_data.climb_speed;
+}
@@ -1,3 +1,13 @@
 mod move_entities;
+mod character;
+mod platform;
+mod water;
+mod ladder;
+mod mode;
 
 pub use move_entities::*;
+pub use character::*;
+pub use platform::*;
+pub use water::*;
+pub use ladder::*;
+pub use mode::*;
@@ -0,0 +1,137 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{CharacterInput, CharacterVelocity, GroundedState, JumpState, Ladder, LadderState, MovementData, WaterState};
+
+const SURFACE_TOLERANCE: f32 = 0.1; // how close to the floating depth counts as being at the surface
+const LADDER_FACING_ANGLE: f32 = 60.0; // looking further away from the ladder does not grab it
+const REGRAB_FRAMES: u32 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MovementMode {
+    Walking,
+    /// no gravity, the input moves the entity up and down directly
+    Flying,
+    /// moving in all directions through water
+    Swimming,
+    /// moving up and down a ladder
+    Climbing,
+}
+
+impl Default for MovementMode {
+    fn default() -> Self {
+        MovementMode::Walking
+    }
+}
+
+/// the ladder the entity touches and looks at, with the side it is climbed from
+fn find_ladder(ladders: &Query<(Entity, &Ladder)>, center: Vec3, radius: f32, look_direction: Vec3) -> Option<(Entity, Ladder, Vec3)> {
+    let look = Vec3::new(look_direction.x(), 0.0, look_direction.z());
+    if look.length_squared() < std::f32::EPSILON {
+        return None;
+    }
+
+    let min_facing = crate::math::degrees_to_radians(LADDER_FACING_ANGLE).cos();
+    for (entity, ladder) in ladders.iter() {
+        let normal = ladder.normal_towards(center);
+        if ladder.touches(center, radius) && center.y() <= ladder.top() && look.normalize().dot(-normal) >= min_facing {
+            return Some((entity, *ladder, normal));
+        }
+    }
+    None
+}
+
+// --- Runs before the kinematic entities are moved ---
+
+/// Decides how a character moves this tick. Flying is toggled by the input directly, everything else
+/// follows from where the character is.
+pub fn update_movement_mode(
+    ladders: Query<(Entity, &Ladder)>,
+    mut entities: Query<(&MovementData, &GroundedState, &CharacterInput, &Transform, Option<&WaterState>, Option<&mut LadderState>, &mut MovementMode, &mut JumpState, &mut CharacterVelocity)>,
+) {
+    for (movement_data, grounded_state, input, transform, water_state, ladder_state, mut mode, mut jump_state, mut velocity) in entities.iter_mut() {
+        let center = transform.translation;
+        let deep_in_water = water_state.map(|water| water.volume.is_some() && water.depth > movement_data.swim_depth).unwrap_or(false);
+
+        let mut ladder_state = ladder_state;
+        let ladder = match &mut ladder_state {
+            Some(ladder_state) if ladder_state.regrab_frames > 0 => {
+                ladder_state.regrab_frames -= 1;
+                None
+            },
+            Some(_) => find_ladder(&ladders, center, movement_data.radius, input.look_direction),
+            None => None,
+        };
+
+        let next = match *mode {
+            MovementMode::Flying => MovementMode::Flying,
+            MovementMode::Walking | MovementMode::Swimming if ladder.is_some() => MovementMode::Climbing,
+            MovementMode::Walking if deep_in_water => MovementMode::Swimming,
+            MovementMode::Walking => MovementMode::Walking,
+            MovementMode::Swimming => {
+                let water_state = water_state.cloned().unwrap_or_default();
+                let at_surface = water_state.depth <= movement_data.swim_depth + SURFACE_TOLERANCE;
+                let on_shore = grounded_state.is_grounded && water_state.depth < movement_data.swim_depth * 0.5;
+
+                if water_state.volume.is_none() || on_shore {
+                    MovementMode::Walking
+                } else if at_surface && input.jump_pressed {
+                    velocity.0.set_y(movement_data.jump_speed);
+                    jump_state.is_jumping = true;
+                    MovementMode::Walking
+                } else {
+                    MovementMode::Swimming
+                }
+            },
+            MovementMode::Climbing => match ladder_state.as_mut() {
+                Some(ladder_state) => {
+                    let normal = ladder_state.normal;
+                    let forward = input.wish_direction.dot(-normal);
+                    let current = ladders.iter().find(|(entity, _)| Some(*entity) == ladder_state.ladder).map(|(_, ladder)| *ladder);
+                    let still_on_ladder = current.map(|ladder| ladder.touches(center, movement_data.radius)).unwrap_or(false);
+                    let above_top = current.map(|ladder| center.y() > ladder.top()).unwrap_or(false);
+
+                    if input.jump_pressed {
+                        // push off backwards
+                        velocity.0 = normal * movement_data.climb_jump_push + Vec3::unit_y() * movement_data.jump_speed;
+                        jump_state.is_jumping = true;
+                        ladder_state.regrab_frames = REGRAB_FRAMES;
+                        MovementMode::Walking
+                    } else if above_top {
+                        // climb over the top onto whatever the ladder leads to
+                        velocity.0 = (Vec3::unit_y() - normal) * movement_data.climb_speed;
+                        ladder_state.regrab_frames = REGRAB_FRAMES;
+                        MovementMode::Walking
+                    } else if grounded_state.is_grounded && forward < 0.0 {
+                        // stepping off at the bottom
+                        ladder_state.regrab_frames = REGRAB_FRAMES;
+                        MovementMode::Walking
+                    } else if !still_on_ladder {
+                        MovementMode::Walking
+                    } else {
+                        MovementMode::Climbing
+                    }
+                },
+                None => MovementMode::Walking,
+            },
+        };
+
+        if let Some(ladder_state) = ladder_state.as_mut() {
+            if next == MovementMode::Climbing {
+                if let Some((entity, _, normal)) = ladder {
+                    ladder_state.ladder = Some(entity);
+                    ladder_state.normal = normal;
+                }
+            } else {
+                ladder_state.ladder = None;
+            }
+        }
+
+        if next != *mode {
+            if next == MovementMode::Climbing || next == MovementMode::Swimming {
+                *jump_state = JumpState::default();
+            }
+            *mode = next;
+        }
+    }
+}
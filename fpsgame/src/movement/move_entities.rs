@@ -1,6 +1,9 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::physics::{PrimitiveIntersection, primitive::Sphere};
+use crate::{math::{Ray, degrees_to_radians, radians_to_degrees}, physics::{PrimitiveIntersection, primitive::Sphere}};
+use crate::physics::InstanceId;
+use super::CharacterVelocity;
 
 #[derive(Debug, Default, Clone)]
 pub struct Kinematic;
@@ -8,11 +11,92 @@ pub struct Kinematic;
 #[derive(Debug, Default, Clone)]
 pub struct Movement(pub Vec3);
 
-#[derive(Debug, Default, Clone)]
+/// Missing fields in profile files fall back to the player defaults
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MovementData {
     pub height: f32,
+    /// Radius of the collision sphere, smaller while crouching
     pub radius: f32,
+    /// Height offset from which raycasts to the ground are made
     pub raycast_offset: f32,
+    /// Steepest slope in degrees the entity can still stand on
+    pub max_slope_angle: f32,
+    /// Obstacles up to this height are stepped onto instead of blocking
+    pub step_height: f32,
+    /// Upwards velocity when jumping
+    pub jump_speed: f32,
+    pub gravity: f32,
+    /// Frames after leaving the ground in which jumping is still possible
+    pub coyote_frames: u32,
+    /// Frames a jump pressed in the air is remembered for until landing
+    pub jump_buffer_frames: u32,
+    /// Fraction of `jump_speed` the upwards velocity is cut to when jump is released early
+    pub jump_cut: f32,
+    /// Horizontal speed reached when walking
+    pub max_speed: f32,
+    pub ground_acceleration: f32,
+    pub ground_friction: f32,
+    pub air_acceleration: f32,
+    /// How fast the velocity turns towards the input direction while in the air
+    pub air_control: f32,
+    pub crouch_radius: f32,
+    /// Movement speed is multiplied by this while crouching
+    pub crouch_speed_factor: f32,
+    /// Movement speed is multiplied by this while sprinting
+    pub sprint_speed_factor: f32,
+    pub max_stamina: f32,
+    /// Stamina used per second of sprinting
+    pub stamina_drain: f32,
+    /// Stamina regained per second once the regen delay is over
+    pub stamina_regen: f32,
+    /// Seconds after sprinting before stamina regenerates
+    pub stamina_regen_delay: f32,
+    pub swim_speed: f32,
+    pub swim_acceleration: f32,
+    /// How quickly the water slows a swimming entity down
+    pub swim_friction: f32,
+    /// How far below the surface the center has to be to start swimming, also the depth it floats at
+    pub swim_depth: f32,
+    /// Speed going up and down ladders
+    pub climb_speed: f32,
+    /// Speed away from the ladder when jumping off of it
+    pub climb_jump_push: f32,
+}
+
+impl Default for MovementData {
+    fn default() -> Self {
+        Self {
+            height: 1.6,
+            radius: 1.0,
+            raycast_offset: 1.0,
+            max_slope_angle: 45.0,
+            step_height: 0.5,
+            jump_speed: 8.0,
+            gravity: 20.0,
+            coyote_frames: 6,
+            jump_buffer_frames: 6,
+            jump_cut: 0.4,
+            max_speed: 10.0,
+            ground_acceleration: 10.0,
+            ground_friction: 6.0,
+            air_acceleration: 1.0,
+            air_control: 2.0,
+            crouch_radius: 0.5,
+            crouch_speed_factor: 0.5,
+            sprint_speed_factor: 1.6,
+            max_stamina: 100.0,
+            stamina_drain: 25.0,
+            stamina_regen: 20.0,
+            stamina_regen_delay: 1.0,
+            swim_speed: 5.0,
+            swim_acceleration: 6.0,
+            swim_friction: 3.0,
+            swim_depth: 0.5,
+            climb_speed: 4.0,
+            climb_jump_push: 6.0,
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -22,6 +106,14 @@ pub struct GroundedState {
     pub is_on_slope: bool,
     pub was_on_slope: bool,
     pub frames_since_grounded: u32,
+    /// Normal of the ground below the entity, zero if there is none
+    pub ground_normal: Vec3,
+    /// Angle of the ground below the entity in degrees
+    pub slope_angle: f32,
+    /// The moving collision instance the entity stands on, `None` on static ground
+    pub ground_instance: Option<InstanceId>,
+    /// Radians the ground turned the entity around the up axis this tick
+    pub ground_rotation: f32,
 }
 
 #[derive(Debug, Clone)]
@@ -36,13 +128,32 @@ pub struct RigidBody {
     pub position: Vec3,
 }
 
+/// Number of physics ticks simulated so far
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PhysicsTick(pub u64);
+
+impl RigidBody {
+    /// Bodies this slow are considered at rest
+    pub fn is_sleeping(&self) -> bool {
+        self.velocity.length_squared() < SLEEP_VELOCITY * SLEEP_VELOCITY
+    }
+}
+
 #[derive(Debug)]
 pub struct Collider {
     pub sphere: Sphere,
 }
 
-const FIXED_UPDATE: f32 = 0.016;
+pub(crate) const FIXED_UPDATE: f32 = 0.016;
 const ITERATIONS: usize = 4;
+pub(super) const DEPENETRATION_ITERATIONS: usize = 4;
+const SLIDE_ITERATIONS: usize = 4;
+const MAX_SUBSTEP: f32 = 0.25; // move at maximum this far at once to avoid tunneling
+const RELOAD_DEPENETRATION_ITERATIONS: usize = 32;
+const GROUND_PROBE_DISTANCE: f32 = 0.1;
+const MIN_SLOPE_ANGLE: f32 = 1.0; // flatter ground does not count as a slope
+const STEP_BLOCKED_FRACTION: f32 = 0.9; // try stepping up if we made less progress than this
+const SLEEP_VELOCITY: f32 = 0.05;
 
 // --- All force modifies
 // --- All systems that modify force must run before velocity update ---
@@ -71,6 +182,10 @@ pub fn resolve_collisions(world: Res<crate::physics::World>, mut entities: Query
         let query = Sphere::new(rb.position + collider.sphere.center, collider.sphere.radius);
         let intersections: Vec<PrimitiveIntersection> = world.collide_sphere_all(&query).collect();
 
+        for intersection in &intersections {
+            crate::physics::debug::record_contact(intersection.position, intersection.surface_normal, intersection.penetration_depth);
+        }
+
         for _ in 0..ITERATIONS {
             for intersection in &intersections {
                 let relative_velocity = -rb.velocity;
@@ -114,30 +229,387 @@ pub fn update_rigid_body_transforms(mut entities: Query<(&RigidBody, &mut Transf
     }
 }
 
-pub fn move_kinematic_entities(world: Res<crate::physics::World>, mut entities: Query<(&Kinematic, &MovementData, &mut Movement, &mut GroundedState, &mut Transform)>) {
-    for (_, movement_data, mut movement, grounded_state, mut transform) in entities.iter_mut() {
-        move_entity(movement.0, &world, &mut transform);
+// --- The tick advances once all physics systems ran ---
+pub fn advance_physics_tick(mut tick: ResMut<PhysicsTick>) {
+    tick.0 += 1;
+}
+
+pub fn move_kinematic_entities(
+    world: Res<crate::physics::World>,
+    mut entities: Query<(&Kinematic, &MovementData, &mut Movement, &mut GroundedState, &mut Transform, Option<&mut CharacterVelocity>)>,
+) {
+    for (_, movement_data, mut movement, mut grounded_state, mut transform, velocity) in entities.iter_mut() {
+        let hit_normals = move_character(movement.0, movement_data, &mut grounded_state, &world, &mut transform);
+
+        // walls and ceilings take away the momentum going into them, the ground is handled by the grounded state
+        if let Some(mut velocity) = velocity {
+            let max_slope_cos = degrees_to_radians(movement_data.max_slope_angle).cos();
+            let blocking: Vec<Vec3> = hit_normals.into_iter()
+                .filter(|normal| !grounded_state.is_grounded || normal.y() < max_slope_cos)
+                .collect();
+            velocity.0 = clip_to_planes(velocity.0, &blocking);
+        }
+
         movement.0 = Vec3::zero();
     }
 }
 
-fn move_entity(movement: Vec3, world: &crate::physics::World, transform: &mut Transform) {
-    let mut distance_to_move = movement.length();
+/// moves a kinematic entity by `movement` and updates its grounded state afterwards,
+/// returns the normals of everything that was hit on the way
+pub fn move_character(movement: Vec3, movement_data: &MovementData, grounded_state: &mut GroundedState, world: &crate::physics::World, transform: &mut Transform) -> Vec<Vec3> {
+    let hit_normals = move_entity(movement, movement_data, grounded_state.is_grounded, world, transform);
+    update_grounded_state(movement_data, grounded_state, transform.translation, world);
+    hit_normals
+}
+
+/// removes the part of `vector` going into any of the planes, so it slides along walls and into corners
+pub fn clip_to_planes(vector: Vec3, normals: &[Vec3]) -> Vec3 {
+    let mut clipped = vector;
+
+    for _ in 0..SLIDE_ITERATIONS {
+        let mut changed = false;
+        for normal in normals {
+            let into_plane = clipped.dot(*normal);
+            if into_plane < 0.0 {
+                clipped -= *normal * into_plane;
+                changed = true;
+            }
+        }
 
-    while distance_to_move > std::f32::EPSILON {
-        let to_move = distance_to_move.min(0.25); // move at maximum 0.25 at once to avoid tunneling
+        if !changed {
+            return clipped;
+        }
+    }
 
-        transform.translation += movement.normalize() * to_move;
-        distance_to_move -= to_move;
+    // still pushing into a plane, the only way left is along the crease of the last two planes
+    if normals.len() >= 2 {
+        let crease = normals[normals.len() - 2].cross(normals[normals.len() - 1]);
+        if crease.length_squared() > std::f32::EPSILON {
+            let crease = crease.normalize();
+            return crease * crease.dot(vector);
+        }
+    }
+
+    Vec3::zero()
+}
+
+#[derive(Debug)]
+struct GroundHit {
+    normal: Vec3,
+    slope_angle: f32,
+    /// vertical gap between the sphere and the ground, negative if it sinks in
+    distance: f32,
+    instance: Option<InstanceId>,
+}
 
-        for _ in 0..4 {
-            if let Some(intersection) = world.collide_sphere(&Sphere::new(transform.translation, 1.0)) {
-                transform.translation += intersection.penetration_normal * (intersection.penetration_depth + std::f32::EPSILON);
+/// casts a ray down and returns the ground if it is at most `max_distance` below the sphere
+fn probe_ground(position: Vec3, max_distance: f32, movement_data: &MovementData, world: &crate::physics::World) -> Option<GroundHit> {
+    // a sphere resting on a slope touches it off center, so the ground straight below
+    // the center is further away the steeper the slope is
+    let max_slope = degrees_to_radians(movement_data.max_slope_angle);
+    let origin = position + Vec3::unit_y() * movement_data.raycast_offset;
+    let ray = Ray::new(origin, -Vec3::unit_y(), movement_data.raycast_offset + movement_data.radius / max_slope.cos() + max_distance);
+
+    let hit = world.raycast(&ray).and_then(|intersection| {
+        let slope_angle = radians_to_degrees(intersection.normal.dot(Vec3::unit_y()).min(1.0).acos());
+        let distance = intersection.t - movement_data.raycast_offset - movement_data.radius / degrees_to_radians(slope_angle).cos();
+
+        if distance > max_distance {
+            return None;
+        }
+
+        Some(GroundHit {
+            normal: intersection.normal,
+            slope_angle,
+            distance,
+            instance: intersection.instance,
+        })
+    });
+
+    // the ray misses ledges and step edges the sphere rests on with its side,
+    // whichever ground is closer is the one the sphere would land on
+    match (hit, probe_ground_sphere(position, max_distance, movement_data, world)) {
+        (Some(ray_hit), Some(sphere_hit)) if sphere_hit.distance < ray_hit.distance - std::f32::EPSILON => Some(sphere_hit),
+        (Some(ray_hit), _) => Some(ray_hit),
+        (None, sphere_hit) => sphere_hit,
+    }
+}
+
+/// lowers the sphere by `max_distance` and takes the walkable contact that has to be lifted the most,
+/// edges too steep to stand on are left to the ray so the sphere can roll off of them
+fn probe_ground_sphere(position: Vec3, max_distance: f32, movement_data: &MovementData, world: &crate::physics::World) -> Option<GroundHit> {
+    let lowered = Sphere::new(position - Vec3::unit_y() * max_distance, movement_data.radius);
+
+    world.collide_sphere_all(&lowered)
+        .filter(|intersection| intersection.penetration_normal.y() > std::f32::EPSILON)
+        .map(|intersection| GroundHit {
+            normal: intersection.penetration_normal,
+            slope_angle: radians_to_degrees(intersection.penetration_normal.y().min(1.0).acos()),
+            distance: max_distance - intersection.penetration_depth / intersection.penetration_normal.y(),
+            instance: intersection.instance,
+        })
+        .filter(|hit| hit.slope_angle <= movement_data.max_slope_angle)
+        .min_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap_or(std::cmp::Ordering::Equal))
+}
+
+fn update_grounded_state(movement_data: &MovementData, grounded_state: &mut GroundedState, position: Vec3, world: &crate::physics::World) {
+    grounded_state.was_grounded = grounded_state.is_grounded;
+    grounded_state.was_on_slope = grounded_state.is_on_slope;
+
+    match probe_ground(position, GROUND_PROBE_DISTANCE, movement_data, world) {
+        Some(ground) => {
+            grounded_state.ground_normal = ground.normal;
+            grounded_state.slope_angle = ground.slope_angle;
+            grounded_state.is_on_slope = ground.slope_angle > MIN_SLOPE_ANGLE;
+            grounded_state.is_grounded = ground.slope_angle <= movement_data.max_slope_angle;
+            grounded_state.ground_instance = ground.instance;
+        },
+        None => {
+            grounded_state.ground_normal = Vec3::zero();
+            grounded_state.slope_angle = 0.0;
+            grounded_state.is_on_slope = false;
+            grounded_state.is_grounded = false;
+            grounded_state.ground_instance = None;
+        },
+    }
+
+    if grounded_state.is_grounded {
+        grounded_state.frames_since_grounded = 0;
+    } else {
+        grounded_state.frames_since_grounded = grounded_state.frames_since_grounded.saturating_add(1);
+    }
+}
+
+/// collide and slide, whatever is left of the movement after hitting something is projected
+/// onto the hit planes and moved along them
+fn move_entity(movement: Vec3, movement_data: &MovementData, grounded: bool, world: &crate::physics::World, transform: &mut Transform) -> Vec<Vec3> {
+    let mut remaining = movement;
+    let mut hit_normals = Vec::new();
+
+    while remaining.length() > std::f32::EPSILON {
+        let distance = remaining.length();
+        let step = remaining * (distance.min(MAX_SUBSTEP) / distance);
+        remaining -= step;
+
+        let start = transform.translation;
+        let mut step_normals = Vec::new();
+        transform.translation = depenetrate_collecting(start + step, movement_data.radius, DEPENETRATION_ITERATIONS, world, &mut step_normals);
+
+        // something low is in the way, try to climb onto it instead of getting pushed back
+        if grounded && movement_data.step_height > 0.0 {
+            let horizontal_step = Vec3::new(step.x(), 0.0, step.z());
+            let horizontal_distance = horizontal_step.length();
+
+            if horizontal_distance > std::f32::EPSILON {
+                let direction = horizontal_step / horizontal_distance;
+                let progress = (transform.translation - start).dot(direction);
+
+                if progress < horizontal_distance * STEP_BLOCKED_FRACTION {
+                    if let Some(stepped) = step_up(start, horizontal_step, movement_data, world) {
+                        if (stepped - start).dot(direction) > progress + std::f32::EPSILON {
+                            transform.translation = stepped;
+                            step_normals.clear();
+                        }
+                    }
+                }
             }
         }
 
-        for intersection in world.collide_sphere_all(&Sphere::new(transform.translation, 1.0)) {
-            crate::util::draw_primitives::draw_line_for((intersection.position, intersection.position + (intersection.penetration_normal * intersection.penetration_depth)), 1);
+        for intersection in world.collide_sphere_all(&Sphere::new(transform.translation, movement_data.radius)) {
+            crate::physics::debug::record_contact(intersection.position, intersection.penetration_normal, intersection.penetration_depth);
         }
+
+        hit_normals.extend(step_normals);
+        remaining = clip_to_planes(remaining, &hit_normals);
+    }
+
+    // stay on the ground when walking down steps or ramps instead of floating off of them
+    if grounded && movement.y() <= 0.0 {
+        transform.translation = snap_to_ground(transform.translation, movement_data.step_height, movement_data, world);
+    }
+
+    hit_normals
+}
+
+/// lifts the sphere by `step_height`, moves it and puts it back down on walkable ground
+fn step_up(start: Vec3, horizontal_step: Vec3, movement_data: &MovementData, world: &crate::physics::World) -> Option<Vec3> {
+    let radius = movement_data.radius;
+
+    // a low ceiling limits how far we can be lifted
+    let lifted = depenetrate(start + Vec3::unit_y() * movement_data.step_height, radius, DEPENETRATION_ITERATIONS, world);
+    let moved = depenetrate(lifted + horizontal_step, radius, DEPENETRATION_ITERATIONS, world);
+
+    let ground = probe_ground(moved, movement_data.step_height, movement_data, world)?;
+    if ground.slope_angle > movement_data.max_slope_angle {
+        return None;
+    }
+
+    Some(depenetrate(moved - Vec3::unit_y() * ground.distance.max(0.0), radius, DEPENETRATION_ITERATIONS, world))
+}
+
+fn snap_to_ground(position: Vec3, max_distance: f32, movement_data: &MovementData, world: &crate::physics::World) -> Vec3 {
+    match probe_ground(position, max_distance, movement_data, world) {
+        Some(ground) if ground.distance > 0.0 && ground.slope_angle <= movement_data.max_slope_angle => {
+            depenetrate(position - Vec3::unit_y() * ground.distance, movement_data.radius, DEPENETRATION_ITERATIONS, world)
+        },
+        _ => position,
+    }
+}
+
+/// pushes a sphere out of the world along the deepest penetration, one contact per iteration
+pub fn depenetrate(position: Vec3, radius: f32, iterations: usize, world: &crate::physics::World) -> Vec3 {
+    depenetrate_collecting(position, radius, iterations, world, &mut Vec::new())
+}
+
+/// same as `depenetrate` but remembers the normals it was pushed along
+fn depenetrate_collecting(mut position: Vec3, radius: f32, iterations: usize, world: &crate::physics::World, normals: &mut Vec<Vec3>) -> Vec3 {
+    for _ in 0..iterations {
+        match world.collide_sphere(&Sphere::new(position, radius)) {
+            Some(intersection) => {
+                position += intersection.penetration_normal * (intersection.penetration_depth + std::f32::EPSILON);
+                normals.push(intersection.penetration_normal);
+            },
+            None => break,
+        }
+    }
+
+    position
+}
+
+/// moves kinematic entities out of the geometry after the collision mesh was swapped
+pub fn push_out_of_geometry(
+    mut reader: Local<EventReader<crate::physics::CollisionWorldReloaded>>,
+    reloaded_events: Res<Events<crate::physics::CollisionWorldReloaded>>,
+    world: Res<crate::physics::World>,
+    mut entities: Query<(&Kinematic, &MovementData, &mut Transform)>,
+) {
+    if reader.iter(&reloaded_events).next().is_none() {
+        return;
+    }
+
+    for (_, movement_data, mut transform) in entities.iter_mut() {
+        transform.translation = depenetrate(transform.translation, movement_data.radius, RELOAD_DEPENETRATION_ITERATIONS, &world);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use crate::physics::{self, World, primitive::Triangle};
+    use super::*;
+
+    const STEP_RISE: f32 = 0.3;
+    const STEP_RUN: f32 = 1.0;
+    const STEP_COUNT: usize = 5;
+    const STAIRS_START: f32 = 2.0;
+
+    fn add_floor(triangles: &mut Vec<Triangle>) {
+        triangles.extend(physics::box_triangles(Vec3::new(-20.0, -1.0, -20.0), Vec3::new(40.0, 0.0, 20.0)));
+    }
+
+    /// a staircase going up along +x, followed by a landing
+    fn create_staircase() -> World {
+        let mut triangles = Vec::new();
+        add_floor(&mut triangles);
+
+        for step in 0..STEP_COUNT {
+            let x = STAIRS_START + step as f32 * STEP_RUN;
+            let height = (step + 1) as f32 * STEP_RISE;
+            triangles.extend(physics::box_triangles(Vec3::new(x, 0.0, -5.0), Vec3::new(x + STEP_RUN, height, 5.0)));
+        }
+        let landing_x = STAIRS_START + STEP_COUNT as f32 * STEP_RUN;
+        triangles.extend(physics::box_triangles(Vec3::new(landing_x, 0.0, -5.0), Vec3::new(landing_x + 10.0, STEP_COUNT as f32 * STEP_RISE, 5.0)));
+
+        physics::create_world_from_triangles(triangles)
+    }
+
+    fn create_wall() -> World {
+        let mut triangles = Vec::new();
+        add_floor(&mut triangles);
+        triangles.extend(physics::box_triangles(Vec3::new(STAIRS_START, 0.0, -5.0), Vec3::new(STAIRS_START + 1.0, 2.0, 5.0)));
+
+        physics::create_world_from_triangles(triangles)
+    }
+
+    fn movement_data() -> MovementData {
+        MovementData {
+            height: 1.6,
+            radius: 1.0,
+            raycast_offset: 1.0,
+            max_slope_angle: 45.0,
+            step_height: 0.5,
+            ..Default::default()
+        }
+    }
+
+    fn walk(world: &World, start: Vec3, movement: Vec3, ticks: usize, on_tick: &mut dyn FnMut(&GroundedState, &Transform)) -> Transform {
+        let movement_data = movement_data();
+        let mut grounded_state = GroundedState::default();
+        let mut transform = Transform::from_translation(start);
+
+        // settle first so the first step already knows it's grounded
+        move_character(Vec3::zero(), &movement_data, &mut grounded_state, world, &mut transform);
+
+        for _ in 0..ticks {
+            move_character(movement, &movement_data, &mut grounded_state, world, &mut transform);
+            on_tick(&grounded_state, &transform);
+        }
+
+        transform
+    }
+
+    #[test]
+    fn test_walks_up_stairs() {
+        let world = create_staircase();
+        let top = STEP_COUNT as f32 * STEP_RISE;
+
+        let transform = walk(&world, Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.1, 0.0, 0.0), 120, &mut |_, _| {});
+
+        assert!(transform.translation.x() > STAIRS_START + STEP_COUNT as f32 * STEP_RUN);
+        assert!((transform.translation.y() - (top + 1.0)).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_stays_grounded_walking_down_stairs() {
+        let world = create_staircase();
+        let top = STEP_COUNT as f32 * STEP_RISE;
+        let start = Vec3::new(STAIRS_START + STEP_COUNT as f32 * STEP_RUN + 2.0, top + 1.0, 0.0);
+
+        let transform = walk(&world, start, Vec3::new(-0.1, 0.0, 0.0), 120, &mut |grounded_state, _| {
+            assert!(grounded_state.is_grounded);
+        });
+
+        assert!(transform.translation.x() < STAIRS_START);
+        assert!((transform.translation.y() - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_blocked_by_wall_higher_than_step() {
+        let world = create_wall();
+
+        let transform = walk(&world, Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.1, 0.0, 0.0), 60, &mut |_, transform| {
+            assert!(transform.translation.y() < 1.0 + 0.05);
+        });
+
+        assert!(transform.translation.x() < STAIRS_START - 0.9);
+    }
+
+    #[test]
+    fn test_slides_along_wall() {
+        let world = create_wall();
+
+        let transform = walk(&world, Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.1, 0.0, 0.1), 40, &mut |_, _| {});
+
+        assert!(transform.translation.x() < STAIRS_START - 0.9);
+        assert!((transform.translation.z() - 4.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_clip_to_planes_stops_in_corner() {
+        let normals = [Vec3::new(-1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)];
+
+        assert_eq!(clip_to_planes(Vec3::new(1.0, 0.0, 1.0), &normals), Vec3::zero());
+        assert_eq!(clip_to_planes(Vec3::new(1.0, 1.0, -1.0), &normals), Vec3::new(0.0, 1.0, -1.0));
     }
 }
@@ -0,0 +1,64 @@
+use bevy::prelude::*;
+
+use super::{GroundedState, Kinematic, MovementData, depenetrate, move_entities::{DEPENETRATION_ITERATIONS, FIXED_UPDATE}};
+
+/// Animates the transform of a collision instance, moving it back and forth and spinning it
+#[derive(Debug, Default, Clone)]
+pub struct MovingPlatform {
+    pub origin: Vec3,
+    /// the platform moves between `origin` and `origin + offset`
+    pub offset: Vec3,
+    /// seconds to move there and back again
+    pub period: f32,
+    /// radians per second around the up axis
+    pub angular_speed: f32,
+    pub time: f32,
+}
+
+impl MovingPlatform {
+    /// moves the transform to where the platform is at `time`
+    pub fn place(&self, transform: &mut Transform) {
+        let phase = if self.period > 0.0 {
+            (1.0 - (self.time * std::f32::consts::PI * 2.0 / self.period).cos()) * 0.5
+        } else {
+            0.0
+        };
+
+        transform.translation = self.origin + self.offset * phase;
+        transform.rotation = Quat::from_rotation_y(self.angular_speed * self.time);
+    }
+}
+
+// --- Platforms move before the collision instances are updated ---
+
+pub fn animate_platforms(mut platforms: Query<(&mut MovingPlatform, &mut Transform)>) {
+    for (mut platform, mut transform) in platforms.iter_mut() {
+        platform.time += FIXED_UPDATE;
+        platform.place(&mut transform);
+    }
+}
+
+// --- Riders are carried once the collision instances are updated ---
+
+/// moves everything standing on a platform along with it and pushes out whatever the platforms ran into
+pub fn carry_riders(
+    world: Res<crate::physics::World>,
+    mut entities: Query<(&Kinematic, &MovementData, &mut GroundedState, &mut Transform)>,
+) {
+    for (_, movement_data, mut grounded_state, mut transform) in entities.iter_mut() {
+        grounded_state.ground_rotation = 0.0;
+
+        let instance = grounded_state.ground_instance.and_then(|id| world.get_instance(id));
+        if let Some(instance) = instance {
+            let displacement = instance.get_displacement();
+            let forward = displacement.transform_vector3(Vec3::unit_z());
+            let rotation = forward.x().atan2(forward.z());
+
+            transform.translation = displacement.transform_point3(transform.translation);
+            transform.rotation = Quat::from_rotation_y(rotation) * transform.rotation;
+            grounded_state.ground_rotation = rotation;
+        }
+
+        transform.translation = depenetrate(transform.translation, movement_data.radius, DEPENETRATION_ITERATIONS, &world);
+    }
+}
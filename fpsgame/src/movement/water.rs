@@ -0,0 +1,159 @@
+use std::collections::HashSet;
+use bevy::prelude::*;
+
+use super::{CharacterInput, CharacterVelocity, Collider, Gravity, MovementData, RigidBody, move_entities::FIXED_UPDATE};
+
+const SURFACING_SPEED: f32 = 1.0; // characters without vertical input drift up to the surface this fast
+
+/// Axis aligned box of water
+#[derive(Debug, Clone, Copy)]
+pub struct WaterVolume {
+    pub min: Vec3,
+    pub max: Vec3,
+    /// how strongly submerged bodies are pushed up, 1 cancels out gravity
+    pub buoyancy: f32,
+    /// slows down bodies moving through the water
+    pub drag: f32,
+}
+
+impl WaterVolume {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self {
+            min,
+            max,
+            buoyancy: 1.5,
+            drag: 2.0,
+        }
+    }
+
+    pub fn surface(&self) -> f32 {
+        self.max.y()
+    }
+
+    fn contains_horizontally(&self, point: Vec3) -> bool {
+        point.x() >= self.min.x() && point.x() <= self.max.x() && point.z() >= self.min.z() && point.z() <= self.max.z()
+    }
+
+    /// Fraction of the sphere below the surface, approximated linearly over its height
+    pub fn submerged_fraction(&self, center: Vec3, radius: f32) -> f32 {
+        if !self.contains_horizontally(center) || center.y() - radius > self.max.y() || center.y() + radius < self.min.y() {
+            return 0.0;
+        }
+
+        ((self.max.y() - (center.y() - radius)) / (2.0 * radius)).max(0.0).min(1.0)
+    }
+}
+
+/// Sent when an entity enters or leaves the water
+#[derive(Debug, Clone)]
+pub enum WaterEvent {
+    Entered { entity: Entity, volume: Entity },
+    Left { entity: Entity },
+}
+
+/// The water an entity is in, updated every physics tick
+#[derive(Debug, Default, Clone)]
+pub struct WaterState {
+    pub volume: Option<Entity>,
+    /// how far the center is below the surface, negative above it
+    pub depth: f32,
+    /// fraction of the collision sphere that is under water
+    pub submerged: f32,
+}
+
+/// the volume the sphere is submerged in the most
+fn find_water(volumes: &Query<(Entity, &WaterVolume)>, center: Vec3, radius: f32) -> Option<(Entity, WaterVolume, f32)> {
+    let mut best: Option<(Entity, WaterVolume, f32)> = None;
+    for (entity, volume) in volumes.iter() {
+        let submerged = volume.submerged_fraction(center, radius);
+        if submerged > 0.0 && best.map(|(_, _, best)| submerged > best).unwrap_or(true) {
+            best = Some((entity, *volume, submerged));
+        }
+    }
+    best
+}
+
+// --- Runs at the start of the physics tick ---
+
+/// updates the `WaterState` of characters and sends events for everything that moved in or out of the water
+pub fn detect_water(
+    mut inside: Local<HashSet<Entity>>,
+    mut water_events: ResMut<Events<WaterEvent>>,
+    volumes: Query<(Entity, &WaterVolume)>,
+    mut entities: Query<(Entity, &Transform, Option<&RigidBody>, Option<&Collider>, Option<&MovementData>, Option<&mut WaterState>)>,
+) {
+    let mut still_inside = HashSet::new();
+
+    for (entity, transform, rb, collider, movement_data, water_state) in entities.iter_mut() {
+        let (center, radius) = match (rb, collider, movement_data) {
+            (Some(rb), Some(collider), _) => (rb.position + collider.sphere.center, collider.sphere.radius),
+            (_, _, Some(movement_data)) => (transform.translation, movement_data.radius),
+            _ => continue,
+        };
+
+        let water = find_water(&volumes, center, radius);
+
+        if let Some(mut water_state) = water_state {
+            *water_state = match water {
+                Some((volume_entity, volume, submerged)) => WaterState {
+                    volume: Some(volume_entity),
+                    depth: volume.surface() - center.y(),
+                    submerged,
+                },
+                None => WaterState::default(),
+            };
+        }
+
+        if let Some((volume, ..)) = water {
+            still_inside.insert(entity);
+            if !inside.contains(&entity) {
+                water_events.send(WaterEvent::Entered { entity, volume });
+            }
+        }
+    }
+
+    // also sent for entities that were despawned while in the water
+    for entity in inside.iter() {
+        if !still_inside.contains(entity) {
+            water_events.send(WaterEvent::Left { entity: *entity });
+        }
+    }
+
+    *inside = still_inside;
+}
+
+// --- Forces are applied next to gravity ---
+
+/// pushes submerged bodies up against gravity and slows them down
+pub fn apply_buoyancy(volumes: Query<(Entity, &WaterVolume)>, mut entities: Query<(&Gravity, &Collider, &mut RigidBody)>) {
+    for (gravity, collider, mut rb) in entities.iter_mut() {
+        let center = rb.position + collider.sphere.center;
+        if let Some((_, volume, submerged)) = find_water(&volumes, center, collider.sphere.radius) {
+            let drag = rb.velocity * volume.drag * rb.mass;
+            rb.force -= (gravity.0 * volume.buoyancy + drag) * submerged;
+        }
+    }
+}
+
+// --- Runs before the kinematic entities are moved ---
+
+/// moves freely in all directions, drifts up to the surface without vertical input
+pub fn update_swim_velocity(movement_data: &MovementData, water_state: &WaterState, input: &CharacterInput, velocity: &mut CharacterVelocity) {
+    let wish = input.wish_direction;
+    let wish_speed = movement_data.swim_speed * wish.length().min(1.0);
+    let wish_direction = if wish.length_squared() > std::f32::EPSILON { wish.normalize() } else { Vec3::zero() };
+
+    velocity.0 *= (1.0 - movement_data.swim_friction * FIXED_UPDATE).max(0.0);
+
+    let current_speed = velocity.0.dot(wish_direction);
+    let add_speed = (wish_speed - current_speed).max(0.0).min(movement_data.swim_acceleration * wish_speed * FIXED_UPDATE);
+    velocity.0 += wish_direction * add_speed;
+
+    if wish_direction.y().abs() < std::f32::EPSILON && velocity.0.y() < SURFACING_SPEED {
+        velocity.0.set_y((velocity.0.y() + SURFACING_SPEED * FIXED_UPDATE).min(SURFACING_SPEED));
+    }
+
+    // float with the head above water instead of shooting out of it
+    let max_rise = (water_state.depth - movement_data.swim_depth).max(0.0) / FIXED_UPDATE;
+    velocity.0.set_y(velocity.0.y().min(max_rise));
+}
@@ -6,14 +6,30 @@ pub fn build_bvh(triangles: Vec<Triangle>) -> Bvh {
 
     let bounds: Vec<Bounds> = triangles.iter().map(|triangle| triangle.get_bounds()).collect();
     let indices: Vec<usize> = triangles.iter().enumerate().map(|(i, _)| i).collect();
+    let (nodes, root) = build_nodes(&bounds, &indices);
+
+    Bvh::from_prebuilt(nodes, root, triangles)
+}
+
+/// Builds a BVH without triangles over a subset of `bounds`, leaves reference the index into `bounds`
+pub fn build_top_level(bounds: &[Bounds], primitives: &[usize]) -> Bvh {
+    let (nodes, root) = build_nodes(bounds, primitives);
+
+    Bvh::from_prebuilt(nodes, root, Vec::new())
+}
+
+fn build_nodes(bounds: &[Bounds], primitives: &[usize]) -> (Vec<BvhNode>, Option<usize>) {
     let mut nodes = Vec::new();
 
-    let (root, _) = build_recursive(&triangles, &bounds, &indices, &mut nodes);
+    if primitives.is_empty() {
+        return (nodes, None);
+    }
 
-    Bvh::from_prebuilt(nodes, Some(root), triangles)
+    let (root, _) = build_recursive(bounds, primitives, &mut nodes);
+    (nodes, Some(root))
 }
 
-fn build_recursive(triangles: &[Triangle], triangle_bounds: &[Bounds], primitives: &[usize], nodes: &mut Vec<BvhNode>) -> (usize, Bounds) {
+fn build_recursive(triangle_bounds: &[Bounds], primitives: &[usize], nodes: &mut Vec<BvhNode>) -> (usize, Bounds) {
     match primitives.len() {
         0 => panic!("No primitives were provided"),
         1 => {
@@ -31,8 +47,8 @@ fn build_recursive(triangles: &[Triangle], triangle_bounds: &[Bounds], primitive
         2 => {
             // make simple split
 
-            let (left, left_bounds) = build_recursive(triangles, triangle_bounds, &[primitives[0]], nodes);
-            let (right, right_bounds) = build_recursive(triangles, triangle_bounds, &[primitives[1]], nodes);
+            let (left, left_bounds) = build_recursive(triangle_bounds, &[primitives[0]], nodes);
+            let (right, right_bounds) = build_recursive(triangle_bounds, &[primitives[1]], nodes);
             let bounds = left_bounds.join(&right_bounds);
 
             let index = nodes.len();
@@ -98,8 +114,8 @@ fn build_recursive(triangles: &[Triangle], triangle_bounds: &[Bounds], primitive
                 }
             }
 
-            let (left, _) = build_recursive(triangles, triangle_bounds, &left, nodes);
-            let (right, _) = build_recursive(triangles, triangle_bounds, &right, nodes);
+            let (left, _) = build_recursive(triangle_bounds, &left, nodes);
+            let (right, _) = build_recursive(triangle_bounds, &right, nodes);
 
             let index = nodes.len();
             nodes.push(BvhNode::Branch {
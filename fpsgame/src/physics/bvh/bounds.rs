@@ -66,6 +66,26 @@ impl Bounds {
         }
     }
 
+    /// Returns the bounds enclosing these bounds after they were transformed by `transform`
+    pub fn transform(&self, transform: &Mat4) -> Self {
+        let corners = [
+            Vec3::new(self.min.x(), self.min.y(), self.min.z()),
+            Vec3::new(self.max.x(), self.min.y(), self.min.z()),
+            Vec3::new(self.min.x(), self.max.y(), self.min.z()),
+            Vec3::new(self.max.x(), self.max.y(), self.min.z()),
+            Vec3::new(self.min.x(), self.min.y(), self.max.z()),
+            Vec3::new(self.max.x(), self.min.y(), self.max.z()),
+            Vec3::new(self.min.x(), self.max.y(), self.max.z()),
+            Vec3::new(self.max.x(), self.max.y(), self.max.z()),
+        ];
+
+        let first = transform.transform_point3(corners[0]);
+        corners.iter().skip(1).fold(Self::new(first, first), |bounds, corner| {
+            let corner = transform.transform_point3(*corner);
+            Self::new(bounds.min.min(corner), bounds.max.max(corner))
+        })
+    }
+
     pub fn largest_direction(&self) -> Axis {
         let extents = self.max - self.min;
 
@@ -44,6 +44,14 @@ impl Bvh {
         }
     }
 
+    /// Returns the bounds of the root node, `None` if the BVH is empty
+    pub fn get_bounds(&self) -> Option<Bounds> {
+        self.root.map(|root| match &self.nodes[root] {
+            BvhNode::Branch { bounds, .. } => bounds.clone(),
+            BvhNode::Leaf { bounds, .. } => bounds.clone(),
+        })
+    }
+
     // TODO move primitives/triangles into world
     pub fn get_primitive(&self, index: usize) -> &Triangle {
         &self.triangles[index]
@@ -84,6 +92,34 @@ impl Bvh {
         BvhIterator::new(self, query, stack)
     }
 
+    /// Returns all primitives whose leaf bounds are hit by the ray
+    pub fn query_ray(&self, ray: &Ray) -> Vec<usize> {
+        let mut stack = Vec::new();
+        let mut primitives = Vec::new();
+
+        if let Some(root) = self.root {
+            stack.push(root);
+        }
+
+        while let Some(index) = stack.pop() {
+            match &self.nodes[index] {
+                BvhNode::Branch { bounds, left, right } => {
+                    if bounds.intersects(ray) {
+                        stack.push(*left);
+                        stack.push(*right);
+                    }
+                },
+                BvhNode::Leaf { bounds, primitive } => {
+                    if bounds.intersects(ray) {
+                        primitives.push(*primitive)
+                    }
+                },
+            }
+        }
+
+        primitives
+    }
+
     pub fn intersects(&self, ray: &Ray) -> Vec<Intersection> {
         let mut intersections = Vec::new();
         if let Some(root) = self.root {
@@ -92,6 +128,31 @@ impl Bvh {
         intersections
     }
 
+    /// Returns the bounds of all nodes up to `max_depth` together with their depth
+    pub fn get_bounds_to_depth(&self, max_depth: usize) -> Vec<(usize, Bounds)> {
+        let mut stack = Vec::new();
+        let mut bounds_to_depth = Vec::new();
+
+        if let Some(root) = self.root {
+            stack.push((root, 0));
+        }
+
+        while let Some((index, depth)) = stack.pop() {
+            match &self.nodes[index] {
+                BvhNode::Branch { bounds, left, right } => {
+                    bounds_to_depth.push((depth, bounds.clone()));
+                    if depth < max_depth {
+                        stack.push((*left, depth + 1));
+                        stack.push((*right, depth + 1));
+                    }
+                },
+                BvhNode::Leaf { bounds, .. } => bounds_to_depth.push((depth, bounds.clone())),
+            }
+        }
+
+        bounds_to_depth
+    }
+
     pub fn calculate_cost(&self) -> f32 {
         let mut stack = Vec::new();
         if let Some(root) = self.root {
@@ -0,0 +1,139 @@
+use std::sync::{Mutex, atomic::{AtomicBool, Ordering}};
+use once_cell::sync::Lazy;
+use bevy::prelude::*;
+
+use crate::util::draw_primitives::{draw_box, draw_colored_line_for};
+use super::World;
+
+// the physics code has no access to the ECS, so just like `draw_primitives` it records into
+// global lists which are drained by `draw_physics_debug` every frame. Recording only happens
+// while the matching category is enabled.
+
+static RECORD_SPHERE_QUERIES: AtomicBool = AtomicBool::new(false);
+static RECORD_CONTACTS: AtomicBool = AtomicBool::new(false);
+
+///triangles touched by the last sphere query
+static LAST_SPHERE_QUERY: Lazy<Mutex<Vec<[Vec3; 3]>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+///contacts recorded since the last frame
+static CONTACTS: Lazy<Mutex<Vec<Contact>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+const BVH_DEPTH_COLORS: [Color; 4] = [
+    Color::rgb(1.0, 0.0, 0.0),
+    Color::rgb(1.0, 1.0, 0.0),
+    Color::rgb(0.0, 1.0, 0.0),
+    Color::rgb(0.0, 1.0, 1.0),
+];
+const INSTANCE_COLOR: Color = Color::rgb(1.0, 0.0, 1.0);
+const SPHERE_QUERY_COLOR: Color = Color::rgb(1.0, 0.5, 0.0);
+const CONTACT_NORMAL_COLOR: Color = Color::rgb(0.0, 1.0, 0.0);
+const CONTACT_PENETRATION_COLOR: Color = Color::rgb(1.0, 0.0, 0.0);
+const VELOCITY_COLOR: Color = Color::rgb(0.0, 0.5, 1.0);
+const SLEEPING_COLOR: Color = Color::rgb(0.3, 0.3, 0.3);
+
+#[derive(Debug, Clone)]
+struct Contact {
+    position: Vec3,
+    normal: Vec3,
+    penetration_depth: f32,
+}
+
+/// Which parts of the physics debug layer are drawn
+#[derive(Debug, Default)]
+pub struct PhysicsDebug {
+    pub bvh_bounds: bool,
+    /// BVH nodes deeper than this are not drawn
+    pub bvh_depth: usize,
+    pub sphere_queries: bool,
+    pub contacts: bool,
+    pub bodies: bool,
+}
+
+pub fn begin_sphere_query() {
+    if RECORD_SPHERE_QUERIES.load(Ordering::Relaxed) {
+        LAST_SPHERE_QUERY.lock().unwrap().clear();
+    }
+}
+
+pub fn record_sphere_query_triangle(a: Vec3, b: Vec3, c: Vec3) {
+    if RECORD_SPHERE_QUERIES.load(Ordering::Relaxed) {
+        LAST_SPHERE_QUERY.lock().unwrap().push([a, b, c]);
+    }
+}
+
+pub fn record_contact(position: Vec3, normal: Vec3, penetration_depth: f32) {
+    if RECORD_CONTACTS.load(Ordering::Relaxed) {
+        CONTACTS.lock().unwrap().push(Contact {
+            position,
+            normal,
+            penetration_depth,
+        });
+    }
+}
+
+/// F1 bvh bounds, PageUp/PageDown bvh depth, F2 sphere queries, F3 contacts, F4 rigid bodies
+pub fn toggle_physics_debug(keyboard_input: Res<Input<KeyCode>>, mut debug: ResMut<PhysicsDebug>) {
+    if keyboard_input.just_pressed(KeyCode::F1) {
+        debug.bvh_bounds = !debug.bvh_bounds;
+    }
+    if keyboard_input.just_pressed(KeyCode::PageUp) {
+        debug.bvh_depth += 1;
+    }
+    if keyboard_input.just_pressed(KeyCode::PageDown) && debug.bvh_depth > 0 {
+        debug.bvh_depth -= 1;
+    }
+    if keyboard_input.just_pressed(KeyCode::F2) {
+        debug.sphere_queries = !debug.sphere_queries;
+    }
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        debug.contacts = !debug.contacts;
+    }
+    if keyboard_input.just_pressed(KeyCode::F4) {
+        debug.bodies = !debug.bodies;
+    }
+
+    RECORD_SPHERE_QUERIES.store(debug.sphere_queries, Ordering::Relaxed);
+    RECORD_CONTACTS.store(debug.contacts, Ordering::Relaxed);
+}
+
+pub fn draw_physics_debug(
+    debug: Res<PhysicsDebug>,
+    world: Res<World>,
+    bodies: Query<&crate::movement::RigidBody>,
+) {
+    if debug.bvh_bounds {
+        for (depth, bounds) in world.get_debug_bounds(debug.bvh_depth) {
+            draw_box(bounds.min, bounds.max, BVH_DEPTH_COLORS[depth % BVH_DEPTH_COLORS.len()]);
+        }
+        for bounds in world.get_instance_bounds() {
+            draw_box(bounds.min, bounds.max, INSTANCE_COLOR);
+        }
+    }
+
+    if debug.sphere_queries {
+        for [a, b, c] in LAST_SPHERE_QUERY.lock().unwrap().iter() {
+            draw_colored_line_for((*a, *b), SPHERE_QUERY_COLOR, 1);
+            draw_colored_line_for((*b, *c), SPHERE_QUERY_COLOR, 1);
+            draw_colored_line_for((*c, *a), SPHERE_QUERY_COLOR, 1);
+        }
+    }
+
+    // contacts are drained even when hidden so they don't pile up after toggling
+    for contact in CONTACTS.lock().unwrap().drain(..) {
+        if debug.contacts {
+            draw_colored_line_for((contact.position, contact.position + contact.normal * 0.5), CONTACT_NORMAL_COLOR, 1);
+            draw_colored_line_for((contact.position, contact.position + contact.normal * contact.penetration_depth), CONTACT_PENETRATION_COLOR, 1);
+        }
+    }
+
+    if debug.bodies {
+        for rb in bodies.iter() {
+            if rb.is_sleeping() {
+                let extent = Vec3::splat(0.1);
+                draw_box(rb.position - extent, rb.position + extent, SLEEPING_COLOR);
+            } else {
+                draw_colored_line_for((rb.position, rb.position + rb.velocity * 0.25), VELOCITY_COLOR, 1);
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::{fs, path::PathBuf, sync::{Mutex, mpsc::{self, Receiver}}, thread, time::{Duration, SystemTime}};
+use bevy::prelude::*;
+
+use super::{LevelVolumes, MarkedVolume, World, bvh::Bvh, try_load_level};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Sent after the static collision mesh of the `World` and the `LevelVolumes` were replaced
+#[derive(Debug, Clone)]
+pub struct CollisionWorldReloaded;
+
+/// Watches the collision source file and rebuilds the BVH and volumes on a background thread
+pub struct CollisionReload {
+    receiver: Mutex<Receiver<(Bvh, Vec<MarkedVolume>)>>,
+}
+
+impl CollisionReload {
+    pub fn watch(path: &str) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let path = PathBuf::from(path);
+
+        thread::spawn(move || {
+            let modified = |path: &PathBuf| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+            let mut last_modified: Option<SystemTime> = modified(&path);
+
+            loop {
+                thread::sleep(POLL_INTERVAL);
+
+                let current = modified(&path);
+                if current == last_modified {
+                    continue;
+                }
+                last_modified = current;
+
+                // the file might still be written to, if it fails to load we just wait for the next change
+                match try_load_level(&path.to_string_lossy()) {
+                    Ok(level) => {
+                        if sender.send(level).is_err() {
+                            break; // the app is gone
+                        }
+                    },
+                    Err(error) => println!("Failed to reload collision mesh {:?}: {}", path, error),
+                }
+            }
+        });
+
+        Self {
+            receiver: Mutex::new(receiver),
+        }
+    }
+}
+
+/// swaps in the most recently rebuilt BVH and volumes
+pub fn reload_collision_world(
+    reload: Res<CollisionReload>,
+    mut world: ResMut<World>,
+    mut volumes: ResMut<LevelVolumes>,
+    mut reloaded_events: ResMut<Events<CollisionWorldReloaded>>,
+) {
+    let receiver = reload.receiver.lock().unwrap();
+
+    // only the newest build matters if several changes piled up
+    if let Some((bvh, marked)) = receiver.try_iter().last() {
+        world.set_static_mesh(bvh);
+        volumes.0 = marked;
+        reloaded_events.send(CollisionWorldReloaded);
+        println!("Reloaded collision mesh");
+    }
+}
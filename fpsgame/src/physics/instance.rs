@@ -0,0 +1,182 @@
+use std::{collections::HashMap, sync::Arc};
+use bevy::prelude::*;
+
+use crate::math::Ray;
+use super::{debug, Intersection, PrimitiveIntersection, World, bvh::{Bounds, Bvh}, primitive::Sphere};
+
+#[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
+pub struct InstanceId(pub(crate) usize);
+
+/// A shared bottom level BVH placed into the world with a transform
+#[derive(Debug)]
+pub struct Instance {
+    mesh: Arc<Bvh>,
+    transform: Mat4,
+    /// transform before the last `set_transform`, used to carry things along
+    previous_transform: Mat4,
+    inverse: Mat4,
+    scale: f32,
+    bounds: Bounds,
+}
+
+impl Instance {
+    pub fn new(mesh: Arc<Bvh>, transform: Mat4) -> Self {
+        let mut instance = Self {
+            mesh,
+            transform: Mat4::identity(),
+            previous_transform: Mat4::identity(),
+            inverse: Mat4::identity(),
+            scale: 1.0,
+            bounds: Bounds::new(Vec3::zero(), Vec3::zero()),
+        };
+        instance.set_transform(transform);
+        instance.previous_transform = transform;
+        instance
+    }
+
+    pub fn get_mesh(&self) -> &Arc<Bvh> {
+        &self.mesh
+    }
+
+    pub fn get_transform(&self) -> Mat4 {
+        self.transform
+    }
+
+    pub fn get_bounds(&self) -> &Bounds {
+        &self.bounds
+    }
+
+    /// Maps points from where the instance was to where it is now
+    pub fn get_displacement(&self) -> Mat4 {
+        self.transform * self.previous_transform.inverse()
+    }
+
+    /// The instance did not move since the last update
+    pub fn reset_displacement(&mut self) {
+        self.previous_transform = self.transform;
+    }
+
+    pub fn set_transform(&mut self, transform: Mat4) {
+        // only uniform scale is supported, sphere queries would turn into ellipsoids otherwise
+        self.previous_transform = self.transform;
+        self.transform = transform;
+        self.inverse = transform.inverse();
+        self.scale = transform.transform_vector3(Vec3::unit_x()).length();
+
+        let position = transform.transform_point3(Vec3::zero());
+        self.bounds = match self.mesh.get_bounds() {
+            Some(bounds) => bounds.transform(&transform),
+            None => Bounds::new(position, position),
+        };
+    }
+
+    pub fn raycast(&self, ray: &Ray) -> Vec<Intersection> {
+        // the direction is not normalized so `t` stays the same in world and instance space
+        let local_ray = Ray::new(
+            self.inverse.transform_point3(ray.origin),
+            self.inverse.transform_vector3(ray.direction),
+            ray.length,
+        );
+
+        self.mesh.intersects(&local_ray).into_iter().map(|intersection| {
+            Intersection::new(
+                intersection.t,
+                ray.get_point(intersection.t),
+                self.transform.transform_vector3(intersection.normal).normalize(),
+            )
+        }).collect()
+    }
+
+    pub fn collide_sphere_all(&self, sphere: &Sphere) -> Vec<PrimitiveIntersection> {
+        let local_sphere = Sphere::new(self.inverse.transform_point3(sphere.center), sphere.radius / self.scale);
+
+        self.mesh.query_bounds(&local_sphere.get_bounds()).into_iter().filter_map(|index| {
+            let triangle = self.mesh.get_primitive(index);
+            local_sphere.intersects_triangle(triangle).map(|intersection| {
+                debug::record_sphere_query_triangle(
+                    self.transform.transform_point3(triangle.a),
+                    self.transform.transform_point3(triangle.b),
+                    self.transform.transform_point3(triangle.c),
+                );
+                PrimitiveIntersection::new(
+                    self.transform.transform_point3(intersection.position),
+                    self.transform.transform_vector3(intersection.surface_normal).normalize(),
+                    self.transform.transform_vector3(intersection.penetration_normal).normalize(),
+                    intersection.penetration_depth * self.scale,
+                )
+            })
+        }).collect()
+    }
+}
+
+/// Places a shared collision mesh into the physics world at the entity's transform
+pub struct CollisionInstance {
+    pub mesh: Arc<Bvh>,
+    transform: Option<Mat4>,
+    id: Option<InstanceId>,
+}
+
+impl CollisionInstance {
+    pub fn new(mesh: Arc<Bvh>) -> Self {
+        Self {
+            mesh,
+            transform: None,
+            id: None,
+        }
+    }
+
+    /// the instance in the world once it was added, raycasts report hits with this id
+    pub fn instance_id(&self) -> Option<InstanceId> {
+        self.id
+    }
+
+    /// moves the instance without displacing what stands on it, for jumps like restoring a snapshot
+    pub fn teleport(&mut self, world: &mut World, transform: &Transform) {
+        let matrix = transform.compute_matrix();
+        if let Some(id) = self.id {
+            world.set_instance_transform(id, matrix);
+            world.reset_instance_displacement(id);
+        }
+        self.transform = Some(matrix);
+        // the world is only updated once the caller moved everything, see `World::update_top_level`
+    }
+}
+
+/// adds, moves and removes world instances for entities with a `CollisionInstance`
+pub fn update_collision_instances(
+    mut registered: Local<HashMap<Entity, InstanceId>>,
+    mut world: ResMut<World>,
+    mut entities: Query<(Entity, &mut CollisionInstance, &Transform)>,
+) {
+    let mut alive = HashMap::new();
+
+    for (entity, mut collision_instance, transform) in entities.iter_mut() {
+        let matrix = transform.compute_matrix();
+
+        let id = match registered.get(&entity) {
+            Some(id) => {
+                if collision_instance.transform != Some(matrix) {
+                    world.set_instance_transform(*id, matrix);
+                } else {
+                    world.reset_instance_displacement(*id);
+                }
+                *id
+            },
+            None => world.add_instance(collision_instance.mesh.clone(), matrix),
+        };
+
+        collision_instance.transform = Some(matrix);
+        collision_instance.id = Some(id);
+        alive.insert(entity, id);
+    }
+
+    // everything that is registered but was not seen was despawned or lost its component
+    for (entity, id) in registered.iter() {
+        if !alive.contains_key(entity) {
+            world.remove_instance(*id);
+        }
+    }
+
+    *registered = alive;
+    world.update_top_level();
+}
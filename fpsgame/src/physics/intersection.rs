@@ -1,10 +1,14 @@
 use bevy::math::*;
 
+use super::InstanceId;
+
 #[derive(Debug)]
 pub struct Intersection {
     pub t: f32,
     pub position: Vec3,
     pub normal: Vec3,
+    /// The instance that was hit, `None` for the static level geometry
+    pub instance: Option<InstanceId>,
 }
 
 impl Intersection {
@@ -13,6 +17,7 @@ impl Intersection {
             t,
             position,
             normal,
+            instance: None,
         }
     }
 }
@@ -23,6 +28,8 @@ pub struct PrimitiveIntersection {
     pub surface_normal: Vec3,
     pub penetration_normal: Vec3,
     pub penetration_depth: f32,
+    /// The instance that was hit, `None` for the static level geometry
+    pub instance: Option<InstanceId>,
 }
 
 impl PrimitiveIntersection {
@@ -32,6 +39,7 @@ impl PrimitiveIntersection {
             surface_normal,
             penetration_normal,
             penetration_depth,
+            instance: None,
         }
     }
 }
@@ -4,48 +4,107 @@ mod baking;
 mod world;
 mod util;
 mod intersection;
+mod instance;
+mod hot_reload;
+mod volume;
+pub mod debug;
+#[cfg(test)]
+pub mod test_util;
 
 pub use world::*;
 pub use intersection::*;
+pub use instance::*;
+pub use hot_reload::*;
+pub use volume::*;
 
+use std::error::Error;
 use bevy::math::*;
 use gltf;
 
-use self::primitive::Triangle;
+use self::{bvh::Bvh, primitive::Triangle};
 
-pub fn create_bvh_from_gltf(path: &str) -> World {
-    let (document, buffer, ..) = gltf::import(path).unwrap();
+/// The static collision world and the volumes marked in the level, panics if it can not be loaded
+pub fn load_level(path: &str) -> (World, LevelVolumes) {
+    let (bvh, volumes) = try_load_level(path).unwrap();
+    (World::new(bvh), LevelVolumes(volumes))
+}
+
+pub fn create_world_from_triangles(triangles: Vec<Triangle>) -> World {
+    World::new(baking::build_bvh(triangles))
+}
+
+/// The triangles of a closed box
+pub fn box_triangles(min: Vec3, max: Vec3) -> Vec<Triangle> {
+    let corner = |x: bool, y: bool, z: bool| Vec3::new(
+        if x { max.x() } else { min.x() },
+        if y { max.y() } else { min.y() },
+        if z { max.z() } else { min.z() },
+    );
+
+    let faces = [
+        [corner(false, false, false), corner(false, true, false), corner(false, true, true), corner(false, false, true)],
+        [corner(true, false, false), corner(true, false, true), corner(true, true, true), corner(true, true, false)],
+        [corner(false, false, false), corner(false, false, true), corner(true, false, true), corner(true, false, false)],
+        [corner(false, true, false), corner(true, true, false), corner(true, true, true), corner(false, true, true)],
+        [corner(false, false, false), corner(true, false, false), corner(true, true, false), corner(false, true, false)],
+        [corner(false, false, true), corner(false, true, true), corner(true, true, true), corner(true, false, true)],
+    ];
+
+    let mut triangles = Vec::new();
+    for [a, b, c, d] in faces.iter() {
+        triangles.push(Triangle::new(*a, *b, *c));
+        triangles.push(Triangle::new(*a, *c, *d));
+    }
+
+    triangles
+}
+
+/// A closed box, meant to be placed into the world as an instance
+pub fn create_box_mesh(min: Vec3, max: Vec3) -> Bvh {
+    baking::build_bvh(box_triangles(min, max))
+}
+
+/// Imports the level once for the collision mesh and the volumes marked in it. Fails on files that
+/// can not be imported and on primitives that are not indexed triangles.
+pub fn try_load_level(path: &str) -> Result<(Bvh, Vec<MarkedVolume>), Box<dyn Error>> {
+    let (document, buffers, ..) = gltf::import(path)?;
     let mut triangles = Vec::new();
+    let mut volumes = Vec::new();
 
     for scene in document.scenes() {
         for node in scene.nodes() {
-            load_recursive(&node, &buffer, &mut triangles);
+            load_recursive(&node, &buffers, &mut triangles)?;
+            volume::load_volumes_recursive(&node, &buffers, Mat4::identity(), &mut volumes);
         }
     }
 
-    World::new(baking::build_bvh(triangles))
+    Ok((baking::build_bvh(triangles), volumes))
 }
 
-pub fn load_recursive(node: &gltf::Node, buffers: &[gltf::buffer::Data], triangles: &mut Vec<Triangle>) {
-    if let Some(mesh) = node.mesh() {
+pub fn load_recursive(node: &gltf::Node, buffers: &[gltf::buffer::Data], triangles: &mut Vec<Triangle>) -> Result<(), Box<dyn Error>> {
+    // trigger volumes like water are loaded separately by `load_volumes_recursive`
+    if let (false, Some(mesh)) = (is_volume_node(node), node.mesh()) {
         // TODO support transform?
         for primitive in mesh.primitives() {
             let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
 
-            let positions: Vec<Vec3> = reader.read_positions().expect("primitive must have POSITION attribute").map(|v| Vec3::new(v[0], v[1], v[2])).collect();
-            let indices: Vec<u32> = reader.read_indices().expect("primitive must have indices").into_u32().collect();
+            let positions: Vec<Vec3> = reader.read_positions().ok_or("primitive must have POSITION attribute")?.map(|v| Vec3::new(v[0], v[1], v[2])).collect();
+            let indices: Vec<u32> = reader.read_indices().ok_or("primitive must have indices")?.into_u32().collect();
+            let position = |index: u32| positions.get(index as usize).copied().ok_or("primitive index out of bounds");
 
-            for triangle_indices in indices.chunks(3) {
+            for triangle_indices in indices.chunks_exact(3) {
                 triangles.push(Triangle::new(
-                    positions[triangle_indices[0] as usize],
-                    positions[triangle_indices[1] as usize],
-                    positions[triangle_indices[2] as usize],
+                    position(triangle_indices[0])?,
+                    position(triangle_indices[1])?,
+                    position(triangle_indices[2])?,
                 ));
             }
         }
     }
 
     for child in node.children() {
-        load_recursive(&child, buffers, triangles);
+        load_recursive(&child, buffers, triangles)?;
     }
+
+    Ok(())
 }
@@ -1,6 +1,6 @@
 use bevy::math::*;
 
-use crate::physics::{PrimitiveIntersection, bvh::{Bounds, HasBounds}, util::closest_point_on_line_segment};
+use crate::{math::Ray, physics::{Intersection, PrimitiveIntersection, bvh::{Bounds, HasBounds}, util::closest_point_on_line_segment}};
 use super::Triangle;
 
 #[derive(Debug, Clone)]
@@ -28,6 +28,26 @@ impl Sphere {
         (self.center - other.center).length() < (self.radius + other.radius)
     }
 
+    /// the first point where `ray` enters the sphere, rays starting inside do not hit
+    pub fn intersects_ray(&self, ray: &Ray) -> Option<Intersection> {
+        let to_center = self.center - ray.origin;
+        let closest = to_center.dot(ray.direction);
+        let distance_squared = to_center.length_squared() - closest * closest;
+        let radius_squared = self.radius * self.radius;
+
+        if distance_squared > radius_squared {
+            return None;
+        }
+
+        let t = closest - (radius_squared - distance_squared).sqrt();
+        if t < 0.0 || t > ray.length {
+            return None;
+        }
+
+        let position = ray.get_point(t);
+        Some(Intersection::new(t, position, (position - self.center).normalize()))
+    }
+
     pub fn intersects_triangle(&self, other: &Triangle) -> Option<PrimitiveIntersection> {
         let n = other.get_normal();
         let distance = (self.center - other.a).dot(n);
@@ -0,0 +1,18 @@
+use bevy::math::Vec3;
+
+use super::primitive::Triangle;
+
+/// the two triangles of the quad `a b c d`
+pub fn quad(a: Vec3, b: Vec3, c: Vec3, d: Vec3) -> Vec<Triangle> {
+    vec![Triangle::new(a, b, c), Triangle::new(a, c, d)]
+}
+
+/// a square floor facing up at y = 0, reaching `half_size` from the origin
+pub fn floor(half_size: f32) -> Vec<Triangle> {
+    quad(
+        Vec3::new(-half_size, 0.0, -half_size),
+        Vec3::new(-half_size, 0.0, half_size),
+        Vec3::new(half_size, 0.0, half_size),
+        Vec3::new(half_size, 0.0, -half_size),
+    )
+}
@@ -0,0 +1,62 @@
+use bevy::math::*;
+
+/// Nodes whose name starts with one of these are trigger volumes and not part of the collision mesh
+pub const VOLUME_PREFIXES: &[&str] = &["water", "ladder", "build_zone"];
+
+/// Axis aligned bounds of a trigger volume authored in the level, the name tells what it is
+#[derive(Debug, Clone)]
+pub struct MarkedVolume {
+    pub name: String,
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+/// The volumes marked in the level, replaced whenever the level is reloaded
+#[derive(Debug, Default)]
+pub struct LevelVolumes(pub Vec<MarkedVolume>);
+
+impl MarkedVolume {
+    pub fn is(&self, prefix: &str) -> bool {
+        self.name.to_lowercase().starts_with(prefix)
+    }
+}
+
+pub fn is_volume_node(node: &gltf::Node) -> bool {
+    node.name()
+        .map(|name| VOLUME_PREFIXES.iter().any(|prefix| name.to_lowercase().starts_with(prefix)))
+        .unwrap_or(false)
+}
+
+/// `parent` is the transform of all nodes above, volumes are usually unit cubes that were moved and scaled
+pub(super) fn load_volumes_recursive(node: &gltf::Node, buffers: &[gltf::buffer::Data], parent: Mat4, volumes: &mut Vec<MarkedVolume>) {
+    let transform = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+
+    if let (true, Some(mesh)) = (is_volume_node(node), node.mesh()) {
+        // rotated volumes grow to the bounds of their corners
+        let mut min = Vec3::splat(std::f32::INFINITY);
+        let mut max = Vec3::splat(std::f32::NEG_INFINITY);
+
+        for primitive in mesh.primitives() {
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            if let Some(positions) = reader.read_positions() {
+                for position in positions {
+                    let position = transform.transform_point3(Vec3::new(position[0], position[1], position[2]));
+                    min = min.min(position);
+                    max = max.max(position);
+                }
+            }
+        }
+
+        if min.cmple(max).all() {
+            volumes.push(MarkedVolume {
+                name: node.name().unwrap_or_default().to_string(),
+                min,
+                max,
+            });
+        }
+    }
+
+    for child in node.children() {
+        load_volumes_recursive(&child, buffers, transform, volumes);
+    }
+}
@@ -1,19 +1,118 @@
-use super::{Intersection, PrimitiveIntersection, bvh::{Bvh, BvhIterator}, primitive::Sphere};
+use std::sync::Arc;
+use bevy::math::*;
+
+use super::{debug, Instance, InstanceId, Intersection, PrimitiveIntersection, baking, bvh::{Bounds, Bvh, BvhIterator}, primitive::Sphere};
 use crate::math::Ray;
 
+/// bisection steps refining the first contact of a sphere sweep
+const SWEEP_REFINE_ITERATIONS: usize = 8;
+
 pub struct World {
     bvh: Bvh,
+    instances: Vec<Option<Instance>>,
+    top_level: Bvh,
+    /// instances changed since the top level was built, see `update_top_level`
+    top_level_dirty: bool,
 }
 
 impl World {
     pub fn new(bvh: Bvh) -> Self {
         Self {
             bvh,
+            instances: Vec::new(),
+            top_level: Bvh::new(),
+            top_level_dirty: false,
         }
     }
 
+    /// Replaces the static level geometry, instances are kept
+    pub fn set_static_mesh(&mut self, bvh: Bvh) {
+        self.bvh = bvh;
+    }
+
+    pub fn add_instance(&mut self, mesh: Arc<Bvh>, transform: Mat4) -> InstanceId {
+        let instance = Some(Instance::new(mesh, transform));
+
+        let index = match self.instances.iter().position(|slot| slot.is_none()) {
+            Some(index) => {
+                self.instances[index] = instance;
+                index
+            },
+            None => {
+                self.instances.push(instance);
+                self.instances.len() - 1
+            },
+        };
+
+        self.top_level_dirty = true;
+        InstanceId(index)
+    }
+
+    pub fn set_instance_transform(&mut self, id: InstanceId, transform: Mat4) {
+        if let Some(Some(instance)) = self.instances.get_mut(id.0) {
+            instance.set_transform(transform);
+            self.top_level_dirty = true;
+        }
+    }
+
+    pub fn remove_instance(&mut self, id: InstanceId) -> Option<Instance> {
+        let instance = self.instances.get_mut(id.0).and_then(|slot| slot.take());
+        self.top_level_dirty |= instance.is_some();
+        instance
+    }
+
+    pub fn get_instance(&self, id: InstanceId) -> Option<&Instance> {
+        self.instances.get(id.0).and_then(|slot| slot.as_ref())
+    }
+
+    pub fn reset_instance_displacement(&mut self, id: InstanceId) {
+        if let Some(Some(instance)) = self.instances.get_mut(id.0) {
+            instance.reset_displacement();
+        }
+    }
+
+    pub fn get_debug_bounds(&self, max_depth: usize) -> Vec<(usize, Bounds)> {
+        self.bvh.get_bounds_to_depth(max_depth)
+    }
+
+    pub fn get_instance_bounds(&self) -> Vec<Bounds> {
+        self.instances.iter().filter_map(|slot| slot.as_ref()).map(|instance| instance.get_bounds().clone()).collect()
+    }
+
+    /// Rebuilds the top level over the instances once after a batch of adds, moves and removes,
+    /// queries only see instance changes after this ran
+    pub fn update_top_level(&mut self) {
+        if !self.top_level_dirty {
+            return;
+        }
+        self.top_level_dirty = false;
+
+        let empty = Bounds::new(Vec3::zero(), Vec3::zero());
+        let bounds: Vec<Bounds> = self.instances.iter().map(|slot| match slot {
+            Some(instance) => instance.get_bounds().clone(),
+            None => empty.clone(),
+        }).collect();
+        let alive: Vec<usize> = self.instances.iter().enumerate().filter(|(_, slot)| slot.is_some()).map(|(index, _)| index).collect();
+
+        self.top_level = baking::build_top_level(&bounds, &alive);
+    }
+
+    fn instances_hit_by(&self, ray: &Ray) -> impl Iterator<Item = (InstanceId, &Instance)> {
+        self.top_level.query_ray(ray).into_iter().filter_map(move |index| self.instances[index].as_ref().map(|instance| (InstanceId(index), instance)))
+    }
+
+    fn instances_overlapping(&self, bounds: &Bounds) -> impl Iterator<Item = (InstanceId, &Instance)> {
+        self.top_level.query_bounds(bounds).into_iter().filter_map(move |index| self.instances[index].as_ref().map(|instance| (InstanceId(index), instance)))
+    }
+
     pub fn raycast(&self, ray: &Ray) -> Option<Intersection> {
         let mut intersections = self.bvh.intersects(ray);
+        for (id, instance) in self.instances_hit_by(ray) {
+            intersections.extend(instance.raycast(ray).into_iter().map(|mut intersection| {
+                intersection.instance = Some(id);
+                intersection
+            }));
+        }
 
         if intersections.len() < 1 {
             return None;
@@ -38,10 +137,23 @@ impl World {
         let bounds = sphere.get_bounds();
         let mut max_penetration = std::f32::NEG_INFINITY;
         let mut best_intersection = None;
+        debug::begin_sphere_query();
         for index in self.bvh.query_bounds(&bounds) {
-            if let Some(intersection) = sphere.intersects_triangle(self.bvh.get_primitive(index)) {
+            let triangle = self.bvh.get_primitive(index);
+            if let Some(intersection) = sphere.intersects_triangle(triangle) {
+                debug::record_sphere_query_triangle(triangle.a, triangle.b, triangle.c);
+                if intersection.penetration_depth > max_penetration {
+                    max_penetration = intersection.penetration_depth;
+                    best_intersection = Some(intersection);
+                }
+            }
+        }
+
+        for (id, instance) in self.instances_overlapping(&bounds) {
+            for mut intersection in instance.collide_sphere_all(sphere) {
                 if intersection.penetration_depth > max_penetration {
                     max_penetration = intersection.penetration_depth;
+                    intersection.instance = Some(id);
                     best_intersection = Some(intersection);
                 }
             }
@@ -50,22 +162,64 @@ impl World {
         best_intersection
     }
 
+    /// How far a sphere can move from `origin` along the normalized `direction` before it touches
+    /// anything. Steps along the path and refines the first contact by bisection.
+    pub fn sweep_sphere(&self, origin: Vec3, direction: Vec3, radius: f32, max_distance: f32) -> f32 {
+        let touches = |distance: f32| self.collide_sphere(&Sphere::new(origin + direction * distance, radius)).is_some();
+        if touches(0.0) {
+            return 0.0;
+        }
+
+        // half the radius per step, thin walls can not be skipped
+        let step = (radius * 0.5).max(0.01);
+        let mut free = 0.0;
+        while free < max_distance {
+            let next = (free + step).min(max_distance);
+            if touches(next) {
+                let mut blocked = next;
+                for _ in 0..SWEEP_REFINE_ITERATIONS {
+                    let middle = (free + blocked) * 0.5;
+                    if touches(middle) {
+                        blocked = middle;
+                    } else {
+                        free = middle;
+                    }
+                }
+                return free;
+            }
+            free = next;
+        }
+
+        max_distance
+    }
+
     pub fn collide_sphere_all<'a>(&'a self, sphere: &'a Sphere) -> SphereIntersectionIter<'a> {
-        let iter = self.bvh.query_bounds_iter(sphere.get_bounds());
-        SphereIntersectionIter::new(iter, sphere)
+        let bounds = sphere.get_bounds();
+        debug::begin_sphere_query();
+        let instance_intersections: Vec<PrimitiveIntersection> = self.instances_overlapping(&bounds)
+            .flat_map(|(id, instance)| instance.collide_sphere_all(sphere).into_iter().map(move |mut intersection| {
+                intersection.instance = Some(id);
+                intersection
+            }))
+            .collect();
+
+        let iter = self.bvh.query_bounds_iter(bounds);
+        SphereIntersectionIter::new(iter, sphere, instance_intersections)
     }
 }
 
 pub struct SphereIntersectionIter<'a> {
     inner: BvhIterator<'a>,
     query: &'a Sphere,
+    instance_intersections: std::vec::IntoIter<PrimitiveIntersection>,
 }
 
 impl<'a> SphereIntersectionIter<'a> {
-    pub fn new(inner: BvhIterator<'a>, query: &'a Sphere) -> Self {
+    pub fn new(inner: BvhIterator<'a>, query: &'a Sphere, instance_intersections: Vec<PrimitiveIntersection>) -> Self {
         Self {
             inner,
             query,
+            instance_intersections: instance_intersections.into_iter(),
         }
     }
 }
@@ -75,11 +229,13 @@ impl<'a> Iterator for SphereIntersectionIter<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         while let Some(primitive) = self.inner.next() {
-            if let Some(intersection) = self.query.intersects_triangle(self.inner.get_triangle(primitive)) {
+            let triangle = self.inner.get_triangle(primitive);
+            if let Some(intersection) = self.query.intersects_triangle(triangle) {
+                debug::record_sphere_query_triangle(triangle.a, triangle.b, triangle.c);
                 return Some(intersection);
             }
         }
 
-        None
+        self.instance_intersections.next()
     }
 }
@@ -0,0 +1,117 @@
+use bevy::{prelude::*, render::camera::PerspectiveProjection};
+
+use crate::{camera::CameraRig, controls::{ActionState, Axis}, math::degrees_to_radians, profile::{LookSettings, MovementProfile}, weapon::PhysicsGun};
+use super::Player;
+
+pub const MAX_PITCH: f32 = 0.5 * std::f32::consts::PI - 0.01;
+
+/// Turns raw mouse counts into radians. Zoomed in views turn slower so the crosshair moves
+/// the same distance on screen, wider views than the default do not turn faster.
+pub fn mouse_rotation(counts: Vec2, settings: &LookSettings, fov: f32, base_fov: f32) -> Vec2 {
+    let fov_scale = ((0.5 * fov).tan() / (0.5 * base_fov).tan()).min(1.0);
+    let y_sign = if settings.invert_y { -1.0 } else { 1.0 };
+    Vec2::new(counts.x(), counts.y() * y_sign) * degrees_to_radians(settings.sensitivity) * fov_scale
+}
+
+/// How much of `pending` to apply this frame. Decays exponentially so the total rotation is the
+/// same at any frame rate, it just arrives a little later.
+pub fn smooth_rotation(pending: &mut Vec2, smoothing: f32, delta_seconds: f32) -> Vec2 {
+    let fraction = if smoothing > 0.0 { 1.0 - (-delta_seconds / smoothing).exp() } else { 1.0 };
+    let applied = *pending * fraction;
+    *pending -= applied;
+    applied
+}
+
+pub fn update_look_direction(
+    time: Res<Time>,
+    actions: Res<ActionState>,
+    profiles: Res<Assets<MovementProfile>>,
+    camera_query: Query<&PerspectiveProjection>,
+    rig_query: Query<&CameraRig>,
+    mut player_query: Query<(&mut Player, &Handle<MovementProfile>, Option<&PhysicsGun>)>,
+) {
+    let actions = actions.for_player(&rig_query);
+    let counts = Vec2::new(actions.axis(Axis::LookX), actions.axis(Axis::LookY));
+    let turn = Vec2::new(actions.axis(Axis::TurnX), actions.axis(Axis::TurnY)) * time.delta_seconds;
+
+    for (mut player, profile, gun) in player_query.iter_mut() {
+        // the mouse turns the held body
+        if gun.map(PhysicsGun::is_rotating).unwrap_or(false) {
+            continue;
+        }
+
+        let profile = MovementProfile::get(&profiles, profile);
+        let base_fov = degrees_to_radians(profile.camera.fov);
+        let fov = camera_query.iter().next().map(|projection| projection.fov).unwrap_or(base_fov);
+
+        // sticks already turn at a rate, only the mouse is smoothed
+        player.pending_look += mouse_rotation(counts, &profile.look, fov, base_fov);
+        let rotation = smooth_rotation(&mut player.pending_look, profile.look.smoothing, time.delta_seconds) + turn;
+
+        player.yaw += rotation.x();
+        player.pitch = (player.pitch + rotation.y()).max(-MAX_PITCH).min(MAX_PITCH);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use crate::profile::LookSettings;
+    use super::*;
+
+    const FOV: f32 = std::f32::consts::FRAC_PI_4;
+
+    /// moves the mouse at a constant speed for a second and then waits for smoothing to catch up
+    fn total_rotation(fps: u32, settings: &LookSettings) -> Vec2 {
+        let delta_seconds = 1.0 / fps as f32;
+        let counts_per_second = Vec2::new(1200.0, -300.0);
+        let mut pending = Vec2::zero();
+        let mut total = Vec2::zero();
+
+        for frame in 0..fps * 3 {
+            let counts = if frame < fps { counts_per_second * delta_seconds } else { Vec2::zero() };
+            pending += mouse_rotation(counts, settings, FOV, FOV);
+            total += smooth_rotation(&mut pending, settings.smoothing, delta_seconds);
+        }
+
+        total
+    }
+
+    #[test]
+    fn test_same_rotation_at_30_and_240_fps() {
+        let settings = LookSettings::default();
+        let slow = total_rotation(30, &settings);
+        let fast = total_rotation(240, &settings);
+
+        assert!((slow - fast).length() < 1e-4, "{:?} != {:?}", slow, fast);
+        assert!((slow.x() - degrees_to_radians(1200.0 * settings.sensitivity)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_same_rotation_at_30_and_240_fps_with_smoothing() {
+        let settings = LookSettings {
+            smoothing: 0.05,
+            ..Default::default()
+        };
+        let slow = total_rotation(30, &settings);
+        let fast = total_rotation(240, &settings);
+
+        assert!((slow - fast).length() < 1e-4, "{:?} != {:?}", slow, fast);
+    }
+
+    #[test]
+    fn test_zoom_slows_down_and_invert_flips_pitch() {
+        let settings = LookSettings {
+            invert_y: true,
+            ..Default::default()
+        };
+        let normal = mouse_rotation(Vec2::new(10.0, 10.0), &settings, FOV, FOV);
+        let zoomed = mouse_rotation(Vec2::new(10.0, 10.0), &settings, FOV * 0.5, FOV);
+        let wide = mouse_rotation(Vec2::new(10.0, 10.0), &settings, FOV * 1.5, FOV);
+
+        assert!(normal.y() < 0.0);
+        assert!(zoomed.x() < normal.x());
+        assert_eq!(wide, normal);
+    }
+}
@@ -2,207 +2,247 @@ use bevy::prelude::*;
 use crate::movement::{
     Movement,
     MovementData,
+    MovementMode,
     Kinematic,
     GroundedState,
+    CharacterInput,
+    CharacterVelocity,
+    CrouchState,
+    Encumbrance,
+    JumpState,
+    LadderState,
+    Stamina,
+    WaterState,
 };
 
-use noise::*;
+mod look;
+pub use look::*;
 
-use crate::math::{*, Clamp};
+use crate::camera::{CameraRig, ShakeEvent, ShakeProfile};
+use crate::controls::{Action, ActionState, Axis};
+use crate::profile::MovementProfile;
+use crate::weapon::{PhysicsGun, WeaponDefinitions, Weapons};
 
-const TRAUMA_MIN: f32 = 0.0;
-const TRAUMA_MAX: f32 = 1.0;
-const TRAUMA_POWER: f32 = 2.0;
-const TRAUMA_DECAY: f32 = 2.0;
-const PERLIN_SAMPLE_SIZE: f64 = 10.0;
-const MAX_YAW_IN_RAD: f32 = 0.2; // maximum amount of yaw rotation when shaking 
-const MAX_PITCH_IN_RAD: f32 = 0.1; // maximum amount of pitch rotation when shaking 
-const MAX_ROLL_IN_RAD: f32 = 0.1; // maximum amount of roll rotation when shaking 
+/// trauma of the debug shake, felt by cameras close to the player
+const DEBUG_SHAKE_INTENSITY: f32 = 0.5;
+const DEBUG_SHAKE_RADIUS: f32 = 10.0;
+const PROFILE_PATH: &str = "profiles/player.ron";
 
-pub fn spawn_player(mut commands: Commands) {
+pub fn spawn_player(mut commands: Commands, asset_server: Res<AssetServer>, weapons: Res<WeaponDefinitions>) {
+    let profile = MovementProfile::default();
+
+    // the movement data is replaced by the profile file once it is loaded
     commands.spawn((
         Player::new(4.012901, 0.3168293),
         Transform::from_translation(Vec3::new(-3.1755996, 5.0, 2.4332705)),
         Movement(Vec3::zero()),
-        MovementData::default(), // TODO
-        GroundedState::default(),
+        profile.movement.clone(),
+        GroundedState {
+            frames_since_grounded: 1000, // arbitrarily high on start, pretending the player was floating in air for a while
+            ..Default::default()
+        },
+        MovementMode::Walking,
+        CharacterInput::default(),
+        CharacterVelocity::default(),
+        JumpState::default(),
+        CrouchState::default(),
+        Stamina::new(profile.movement.max_stamina),
+        WaterState::default(),
+        LadderState::default(),
         Kinematic,
-    ));
+    ))
+    .with(asset_server.load::<MovementProfile, _>(PROFILE_PATH))
+    .with(Weapons::new(&weapons))
+    .with(PhysicsGun::default())
+    .with(Encumbrance::default());
 }
 
 pub fn move_player(
-    keyboard_input: Res<Input<KeyCode>>,
+    actions: Res<ActionState>,
     time: Res<Time>,
-    mut query: Query<(&mut Player, &mut Movement)>,
+    profiles: Res<Assets<MovementProfile>>,
+    mut shake_events: ResMut<Events<ShakeEvent>>,
+    rig_query: Query<&CameraRig>,
+    mut query: Query<(&Player, &Transform, &MovementData, &CrouchState, &Handle<MovementProfile>, &mut Movement, &mut MovementMode, &mut CharacterInput)>,
 ) {
-    let mut player_move = Vec3::default();
-    if keyboard_input.pressed(KeyCode::W) {
-         player_move += Vec3::new(0.0, 0.0, 1.0);
-    }
-    if keyboard_input.pressed(KeyCode::A) {
-         player_move += Vec3::new(1.0, 0.0, 0.0);
-    }
-    if keyboard_input.pressed(KeyCode::S) {
-         player_move += Vec3::new(0.0, 0.0, -1.0);
-    }
-    if keyboard_input.pressed(KeyCode::D) {
-         player_move += Vec3::new(-1.0, 0.0, 0.0);
-    }
-    if keyboard_input.pressed(KeyCode::Space) {
-         player_move += Vec3::new(0.0, 1.0, 0.0);
-    }
-    if keyboard_input.pressed(KeyCode::LShift) {
-         player_move += Vec3::new(0.0, -1.0, 0.0);
-    }
+    let actions = actions.for_player(&rig_query);
+
+    // analog sticks give smaller values, keys pressed diagonally are not faster
+    let player_move = clamp_length(Vec3::new(actions.axis(Axis::MoveLeft), 0.0, actions.axis(Axis::MoveForward)), 1.0);
 
-    let delta = time.delta_seconds.min(0.032);
+    for (player, transform, movement_data, crouch_state, profile, mut movement, mut mode, mut input) in query.iter_mut() {
+        let profile = MovementProfile::get(&profiles, profile);
+        let delta = time.delta_seconds.min(profile.max_delta);
+
+        if actions.just_pressed(Action::ToggleFly) {
+            *mode = match *mode {
+                MovementMode::Walking | MovementMode::Swimming | MovementMode::Climbing => MovementMode::Flying,
+                MovementMode::Flying => MovementMode::Walking,
+            };
+        }
+
+        // space jumps when walking and flies up otherwise
+        let mut player_move = player_move;
+        if *mode == MovementMode::Flying {
+            if actions.pressed(Action::Jump) {
+                player_move += Vec3::new(0.0, 1.0, 0.0);
+            }
+            if actions.pressed(Action::Descend) {
+                player_move += Vec3::new(0.0, -1.0, 0.0);
+            }
+        }
+
+        // swimming follows the pitch of the camera, space swims up and control dives
+        if *mode == MovementMode::Swimming {
+            let forward = player_move.z();
+            player_move = Vec3::new(player_move.x(), -forward * player.pitch.sin(), forward * player.pitch.cos());
+            if actions.pressed(Action::Jump) {
+                player_move += Vec3::new(0.0, 1.0, 0.0);
+            }
+            if actions.pressed(Action::Crouch) {
+                player_move += Vec3::new(0.0, -1.0, 0.0);
+            }
+        }
+        input.look_direction = player.get_look_direction();
+        input.jump_pressed = actions.just_pressed(Action::Jump);
+        input.jump_held = actions.pressed(Action::Jump);
+        input.crouch = *mode != MovementMode::Swimming && actions.pressed(Action::Crouch);
+        input.sprint = *mode == MovementMode::Walking && actions.pressed(Action::Sprint);
 
-    for (mut player, mut movement) in query.iter_mut() {
         let sin = player.yaw.sin();
         let cos = player.yaw.cos();
 
-        player_move *= delta * 10.0;
-        // player_move *= Vec3::new(1.0, 2.0, 1.0);
-
         player_move = Vec3::new(
             player_move.x() * cos - player_move.z() * sin,
             player_move.y(),
             player_move.z() * cos + player_move.x() * sin,
         );
 
-        // player_move += -Vec3::new(0.0, 9.81 * delta, 0.0);
-
-        movement.0 = player_move;
+        // walking goes through the velocity of the character, flying moves directly
+        if *mode == MovementMode::Flying {
+            player_move *= delta * profile.fly_speed;
+            if crouch_state.is_crouching {
+                player_move *= movement_data.crouch_speed_factor;
+            }
+            movement.0 = player_move;
+            input.wish_direction = Vec3::zero();
+        } else if *mode == MovementMode::Swimming {
+            input.wish_direction = clamp_length(player_move, 1.0);
+        } else {
+            input.wish_direction = clamp_length(Vec3::new(player_move.x(), 0.0, player_move.z()), 1.0);
+        }
 
-        if keyboard_input.just_pressed(KeyCode::T) {
-            player.add_trauma(0.5);
+        if actions.just_pressed(Action::Shake) {
+            shake_events.send(ShakeEvent {
+                origin: transform.translation,
+                intensity: DEBUG_SHAKE_INTENSITY,
+                radius: DEBUG_SHAKE_RADIUS,
+                profile: ShakeProfile::default(),
+            });
         }
     }
 }
 
+fn clamp_length(vector: Vec3, max: f32) -> Vec3 {
+    let length = vector.length();
+    if length > max { vector * (max / length) } else { vector }
+}
+
 #[derive(Debug)]
 pub struct Player {
     pub yaw: f32,
     pub pitch: f32,
-
-    /// Werether to activate the current action or not
-    pub action: bool,
+    /// Mouse rotation that was input but not applied yet because of smoothing, in radians
+    pub pending_look: Vec2,
 
     /// Total height of the player
     pub height: f32,
 
     /// Height offset at which the camera is placed
     pub camera_height: f32,
-
-    /// Height offset from which raycasts to the ground are made
-    pub raycast_offset: f32,
-
-    /// True if the player is standing on solid ground
-    pub grounded: bool,
-    /// True if the player was standing on solid ground last frame
-    pub was_grounded: bool,
-    /// The number of frames since player was last grounded
-    pub frames_since_grounded: u32,
-
-    /// True if the player is standing on a slope
-    pub on_slope: bool,
-    /// True if the player was standing on a slope last frame
-    pub was_on_slope: bool,
-
-    // trauma the player experienced spanning from [TRAUMA_MIN - TRAUMA_MAX]
-    pub trauma: f32, 
-    pub trauma_yaw: f32,
-    pub trauma_pitch: f32,
-    pub trauma_roll: f32,
 }
 
 impl Player {
     pub fn new(yaw: f32, pitch: f32) -> Self { // <- Self is `Player` because we are in `impl Player`
+        let body = MovementProfile::default().body;
         Self { // <- implicit return because no semicolon
             yaw, // <- this is the short form of `yaw: yaw,`
             pitch,
+            pending_look: Vec2::zero(),
 
-            action: false,
-
-            height: 1.6,
-            camera_height: 1.5,
-            raycast_offset: 1.0,
-
-            grounded: false,
-            was_grounded: false,
-            frames_since_grounded: 1000, // arbitrarily high on start, pretending the player was floating in air for a while
-            on_slope: false,
-            was_on_slope: false,
-
-            trauma: 0.0,
-            trauma_yaw: 0.0,
-            trauma_pitch: 0.0,
-            trauma_roll: 0.0,
+            height: body.height,
+            camera_height: body.camera_height,
         }
     }
 
-    pub fn add_trauma(&mut self, amount: f32) {
-        if amount > TRAUMA_MAX { println!("Trauma amount added was greater than {} and was limited to {}.", TRAUMA_MAX, TRAUMA_MAX) };
-        self.trauma += min(TRAUMA_MAX, self.trauma+amount);
-        self.trauma = self.trauma.clamp_value(0.0, 1.0);
+    /// where the player aims, camera effects like shaking only move the view
+    pub fn get_look_direction(&self) -> Vec3 {
+        look_direction(self.yaw, self.pitch)
     }
+}
 
-    pub fn shake_camera(&mut self, secs_since_startup: f64) {
-        let shake = self.trauma.powf(TRAUMA_POWER); // the amount of shake depending on the amount of trauma 
-        let perlin = Perlin::new();
-        let perlin_noise_yaw = perlin.get([1.0, secs_since_startup*PERLIN_SAMPLE_SIZE]) as f32;
-        let perlin_noise_pitch = perlin.get([2.0, secs_since_startup*PERLIN_SAMPLE_SIZE]) as f32;
-        let perlin_noise_roll = perlin.get([3.0, secs_since_startup*PERLIN_SAMPLE_SIZE]) as f32;
-        self.trauma_yaw = MAX_YAW_IN_RAD * shake * perlin_noise_yaw;
-        self.trauma_pitch = MAX_PITCH_IN_RAD * shake * perlin_noise_pitch;
-        self.trauma_roll = MAX_ROLL_IN_RAD * shake * perlin_noise_roll;
-        self.trauma_yaw = self.trauma_yaw.clamp_value(-MAX_YAW_IN_RAD, MAX_YAW_IN_RAD);
-        self.trauma_pitch = self.trauma_pitch.clamp_value(-MAX_PITCH_IN_RAD, MAX_PITCH_IN_RAD);
-
-        println!("seconds {}, perlin {}, trauma: {}, degrees: {}", secs_since_startup, perlin_noise_yaw, self.trauma, radians_to_degrees(self.trauma_yaw));
-    }
+pub fn look_direction(yaw: f32, pitch: f32) -> Vec3 {
+    let direction = Vec3::new(0.0, 0.0, 1.0);
+    let direction = Vec3::new(
+        direction.x(),
+        direction.y() * pitch.cos() - direction.z() * pitch.sin(),
+        direction.z() * pitch.cos() - direction.y() * pitch.sin(),
+    );
+
+    let direction = Vec3::new(
+        direction.x() * yaw.cos() - direction.z() * yaw.sin(),
+        direction.y(),
+        direction.z() * yaw.cos() - direction.x() * yaw.sin(),
+    );
+
+    direction.normalize()
+}
 
-    pub fn get_look_direction(&self) -> Vec3 {
-        let combined_yaw = self.yaw + self.trauma_yaw;
-        let combined_pitch = self.pitch + self.trauma_pitch;
-        // let combined_roll = self.roll + self.trauma_roll;
-
-        let direction = Vec3::new(0.0, 0.0, 1.0);
-        let direction = Vec3::new(
-            direction.x(),
-            direction.y() * combined_pitch.cos() - direction.z() * combined_pitch.sin(),
-            direction.z() * combined_pitch.cos() - direction.y() * combined_pitch.sin(),
-        );
+/// moves the camera along when the collider is resized for crouching
+pub fn update_camera_height(
+    time: Res<Time>,
+    profiles: Res<Assets<MovementProfile>>,
+    mut player_query: Query<(&mut Player, &CrouchState, &Handle<MovementProfile>)>,
+) {
+    for (mut player, crouch_state, profile) in player_query.iter_mut() {
+        let body = &MovementProfile::get(&profiles, profile).body;
 
-        let direction = Vec3::new(
-            direction.x() * combined_yaw.cos() - direction.z() * combined_yaw.sin(),
-            direction.y(),
-            direction.z() * combined_yaw.cos() - direction.x() * combined_yaw.sin(),
-        );
+        // the center jumped, keep the camera where it was and let it catch up smoothly
+        player.camera_height -= crouch_state.center_shift;
 
-        direction.normalize()
+        let (height, camera_height) = if crouch_state.is_crouching {
+            (body.crouch_height, body.crouch_camera_height)
+        } else {
+            (body.height, body.camera_height)
+        };
+
+        player.height = height;
+        player.camera_height += (camera_height - player.camera_height) * (body.camera_height_speed * time.delta_seconds).min(1.0);
     }
 }
 
-pub fn update_trauma(
-    time: Res<Time>,
-    mut player_query: Query<&mut Player>,
-) {
-    for mut player in player_query.iter_mut() {
-        if player.trauma > 0.0 {
-            player.trauma = TRAUMA_MIN.max(player.trauma - TRAUMA_DECAY*time.delta_seconds);
-            player.shake_camera(time.seconds_since_startup);
-        }
+/// riders of rotating platforms turn along with them
+pub fn turn_with_ground(mut player_query: Query<(&mut Player, &GroundedState)>) {
+    for (mut player, grounded_state) in player_query.iter_mut() {
+        // the look direction is rotated by negative yaw
+        player.yaw -= grounded_state.ground_rotation;
     }
-} 
+}
 
 pub fn shake_when_hit_ground(
-    mut last_grounded: Local<bool>,
-    mut player_query: Query<&mut Player>) {
-    for mut player in player_query.iter_mut() {
-        if !*last_grounded && player.grounded {
-            player.add_trauma(0.5);
+    mut shake_events: ResMut<Events<ShakeEvent>>,
+    profiles: Res<Assets<MovementProfile>>,
+    player_query: Query<(&Player, &Transform, &GroundedState, &Handle<MovementProfile>)>,
+) {
+    for (_, transform, grounded_state, profile) in player_query.iter() {
+        if !grounded_state.was_grounded && grounded_state.is_grounded {
+            let settings = &MovementProfile::get(&profiles, profile).shake;
+            shake_events.send(ShakeEvent {
+                origin: transform.translation,
+                intensity: settings.landing_intensity,
+                radius: settings.landing_radius,
+                profile: settings.landing,
+            });
         }
-        *last_grounded = player.grounded;
     }
-} 
+}
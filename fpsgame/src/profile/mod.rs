@@ -0,0 +1,241 @@
+use std::collections::HashSet;
+use bevy::{asset::{AssetLoader, LoadContext, LoadedAsset}, prelude::*, reflect::TypeUuid, utils::BoxedFuture};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::{camera::ShakeProfile, movement::{CrouchState, MovementData}};
+
+/// used until the profile of an entity finished loading
+static DEFAULT_PROFILE: Lazy<MovementProfile> = Lazy::new(MovementProfile::default);
+
+/// Tuning for how an entity moves and how the camera follows it, loaded from `assets/profiles/*.ron`
+#[derive(Debug, Clone, Serialize, Deserialize, TypeUuid)]
+#[uuid = "a6bd140f-bbac-43df-a3e6-786e085e95bf"]
+#[serde(default)]
+pub struct MovementProfile {
+    pub movement: MovementData,
+    /// units per second when flying
+    pub fly_speed: f32,
+    /// longer frames are clamped to this many seconds so hitches don't teleport the player
+    pub max_delta: f32,
+    pub look: LookSettings,
+    pub body: BodySettings,
+    pub camera: CameraSettings,
+    pub shake: ShakeSettings,
+    pub effects: CameraEffectSettings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LookSettings {
+    /// degrees turned per count of mouse motion, independent of the frame rate
+    pub sensitivity: f32,
+    pub invert_y: bool,
+    /// seconds until most of a mouse movement is applied, 0 turns smoothing off
+    pub smoothing: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BodySettings {
+    pub height: f32,
+    pub crouch_height: f32,
+    /// height offset at which the camera is placed
+    pub camera_height: f32,
+    pub crouch_camera_height: f32,
+    /// how fast the camera moves to its new height when crouching
+    pub camera_height_speed: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraSettings {
+    /// vertical field of view in degrees
+    pub fov: f32,
+    /// field of view while sprinting
+    pub sprint_fov: f32,
+    /// how fast the field of view changes
+    pub fov_speed: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShakeSettings {
+    /// trauma added to cameras right at the player when landing
+    pub landing_intensity: f32,
+    /// cameras further away than this do not feel the landing
+    pub landing_radius: f32,
+    pub landing: ShakeProfile,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraEffectSettings {
+    /// how fast the recoil and field of view kick of weapons wear off
+    pub recoil_recovery: f32,
+    pub fov_kick_recovery: f32,
+    /// how far the view dips per unit per second of falling speed when landing
+    pub landing_dip: f32,
+    pub max_landing_dip: f32,
+    pub landing_recovery: f32,
+    /// distance covered by a single step of the head bob
+    pub bob_step_length: f32,
+    pub bob_height: f32,
+    pub bob_sway: f32,
+    /// degrees the view rolls from one foot to the other
+    pub bob_roll: f32,
+}
+
+impl Default for MovementProfile {
+    fn default() -> Self {
+        Self {
+            movement: MovementData::default(),
+            fly_speed: 10.0,
+            max_delta: 0.032,
+            look: LookSettings::default(),
+            body: BodySettings::default(),
+            camera: CameraSettings::default(),
+            shake: ShakeSettings::default(),
+            effects: CameraEffectSettings::default(),
+        }
+    }
+}
+
+impl Default for LookSettings {
+    fn default() -> Self {
+        Self {
+            sensitivity: 0.08,
+            invert_y: false,
+            smoothing: 0.0,
+        }
+    }
+}
+
+impl Default for BodySettings {
+    fn default() -> Self {
+        Self {
+            height: 1.6,
+            crouch_height: 1.1,
+            camera_height: 1.5,
+            crouch_camera_height: 0.9,
+            camera_height_speed: 10.0,
+        }
+    }
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            fov: 45.0,
+            sprint_fov: 55.0,
+            fov_speed: 8.0,
+        }
+    }
+}
+
+impl Default for ShakeSettings {
+    fn default() -> Self {
+        Self {
+            landing_intensity: 0.5,
+            landing_radius: 10.0,
+            landing: ShakeProfile::default(),
+        }
+    }
+}
+
+impl Default for CameraEffectSettings {
+    fn default() -> Self {
+        Self {
+            recoil_recovery: 10.0,
+            fov_kick_recovery: 8.0,
+            landing_dip: 0.02,
+            max_landing_dip: 0.3,
+            landing_recovery: 8.0,
+            bob_step_length: 2.2,
+            bob_height: 0.05,
+            bob_sway: 0.03,
+            bob_roll: 0.4,
+        }
+    }
+}
+
+impl MovementProfile {
+    /// The loaded profile behind `handle`, or the defaults while it is still loading
+    pub fn get<'a>(profiles: &'a Assets<MovementProfile>, handle: &Handle<MovementProfile>) -> &'a MovementProfile {
+        profiles.get(handle).unwrap_or(&DEFAULT_PROFILE)
+    }
+}
+
+#[derive(Default)]
+pub struct MovementProfileLoader;
+
+impl AssetLoader for MovementProfileLoader {
+    fn load<'a>(&'a self, bytes: &'a [u8], load_context: &'a mut LoadContext) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let profile: MovementProfile = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(profile));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ron"]
+    }
+}
+
+/// copies the movement values of a profile into the entity once it loaded and whenever the file changes
+pub fn apply_movement_profiles(
+    mut reader: Local<EventReader<AssetEvent<MovementProfile>>>,
+    mut applied: Local<HashSet<Entity>>,
+    profile_events: Res<Events<AssetEvent<MovementProfile>>>,
+    profiles: Res<Assets<MovementProfile>>,
+    mut entities: Query<(Entity, &Handle<MovementProfile>, &mut MovementData, Option<&mut CrouchState>)>,
+) {
+    let mut changed = HashSet::new();
+    for event in reader.iter(&profile_events) {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                changed.insert(handle.clone());
+            },
+            AssetEvent::Removed { .. } => {},
+        }
+    }
+
+    for (entity, handle, mut movement_data, crouch_state) in entities.iter_mut() {
+        if applied.contains(&entity) && !changed.contains(handle) {
+            continue;
+        }
+
+        let profile = match profiles.get(handle) {
+            Some(profile) => profile,
+            None => continue,
+        };
+
+        *movement_data = profile.movement.clone();
+
+        // a crouching entity keeps its small collider until it stands up
+        if let Some(mut crouch_state) = crouch_state {
+            if crouch_state.is_crouching {
+                crouch_state.standing_radius = movement_data.radius;
+                movement_data.radius = movement_data.crouch_radius;
+            }
+        }
+
+        applied.insert(entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_fields_fall_back_to_player_defaults() {
+        let pet: MovementProfile = ron::de::from_str(include_str!("../../assets/profiles/pet.ron")).unwrap();
+        assert_eq!(pet.movement.radius, 0.3);
+
+        let partial: MovementProfile = ron::de::from_str("(movement: (radius: 0.3))").unwrap();
+        assert_eq!(partial.movement.radius, 0.3);
+        assert_eq!(partial.movement.height, MovementData::default().height);
+    }
+}
@@ -0,0 +1,936 @@
+use std::{collections::HashMap, error::Error, fs};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::{CameraShake, ShakeProfile},
+    lifetime::Lifetime,
+    movement::{CharacterVelocity, Collider, CrouchState, Gravity, GroundedState, JumpState, Kinematic, Movement, MovementData, MovementMode, MovingPlatform, PhysicsTick, RigidBody, Stamina, LadderState},
+    physics::{CollisionInstance, InstanceId, primitive::Sphere},
+    player::Player,
+    weapon::{Projectile, ProjectileDefinition, Weapons},
+};
+
+const SNAPSHOT_PATH: &str = "./snapshot.ron";
+
+fn to_array(vector: Vec3) -> [f32; 3] {
+    [vector.x(), vector.y(), vector.z()]
+}
+
+fn from_array(array: [f32; 3]) -> Vec3 {
+    Vec3::new(array[0], array[1], array[2])
+}
+
+/// FNV-1a over the bit patterns, stable across builds unlike the std hasher
+pub(crate) struct Checksum(pub u64);
+
+impl Checksum {
+    pub fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        for byte in value.to_le_bytes().iter() {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.write_u64(value.to_bits() as u64);
+    }
+
+    pub fn write_array(&mut self, array: &[f32; 3]) {
+        for value in array {
+            self.write_f32(*value);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RigidBodySnapshot {
+    pub mass: f32,
+    pub cor: f32,
+    pub force: [f32; 3],
+    pub velocity: [f32; 3],
+    pub position: [f32; 3],
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColliderSnapshot {
+    pub center: [f32; 3],
+    pub radius: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroundedStateSnapshot {
+    pub is_grounded: bool,
+    pub was_grounded: bool,
+    pub is_on_slope: bool,
+    pub was_on_slope: bool,
+    pub frames_since_grounded: u32,
+    pub ground_normal: [f32; 3],
+    pub slope_angle: f32,
+    pub ground_instance: Option<usize>,
+    pub ground_rotation: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JumpStateSnapshot {
+    pub buffered_frames: Option<u32>,
+    pub is_jumping: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CrouchStateSnapshot {
+    pub is_crouching: bool,
+    pub standing_radius: f32,
+    /// the current radius of the collision sphere in `MovementData`
+    pub radius: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StaminaSnapshot {
+    pub current: f32,
+    pub regen_delay: f32,
+    pub is_sprinting: bool,
+    pub exhausted: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LadderStateSnapshot {
+    pub ladder: Option<u64>,
+    pub normal: [f32; 3],
+    pub regrab_frames: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectileSnapshot {
+    pub shooter: u64,
+    pub definition: ProjectileDefinition,
+    pub bounces: u32,
+    pub age: f32,
+    pub last_position: [f32; 3],
+    pub touching: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WeaponSlotSnapshot {
+    pub ammo: u32,
+    pub cooldown: f32,
+    pub reload: Option<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WeaponsSnapshot {
+    pub slots: Vec<WeaponSlotSnapshot>,
+    pub current: usize,
+    pub spread_state: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CameraShakeSnapshot {
+    /// trauma and profile of every shake
+    pub shakes: Vec<(f32, ShakeProfile)>,
+    /// where the shakes are in the noise
+    pub time: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerSnapshot {
+    pub yaw: f32,
+    pub pitch: f32,
+    /// mouse rotation still to be applied by the smoothing
+    pub pending_look: [f32; 2],
+}
+
+/// Simulation state of a single entity, components the entity does not have are `None`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EntitySnapshot {
+    pub entity: u64,
+    pub rigid_body: Option<RigidBodySnapshot>,
+    pub collider: Option<ColliderSnapshot>,
+    pub gravity: Option<[f32; 3]>,
+    pub lifetime: Option<u32>,
+    pub grounded_state: Option<GroundedStateSnapshot>,
+    pub movement: Option<[f32; 3]>,
+    /// kinematic entities keep their position in the transform
+    pub translation: Option<[f32; 3]>,
+    pub velocity: Option<[f32; 3]>,
+    pub jump_state: Option<JumpStateSnapshot>,
+    pub movement_mode: Option<MovementMode>,
+    pub crouch_state: Option<CrouchStateSnapshot>,
+    pub stamina: Option<StaminaSnapshot>,
+    pub ladder_state: Option<LadderStateSnapshot>,
+    /// animation time of a moving platform
+    pub platform_time: Option<f32>,
+    pub player: Option<PlayerSnapshot>,
+    pub projectile: Option<ProjectileSnapshot>,
+    pub weapons: Option<WeaponsSnapshot>,
+    pub camera_shake: Option<CameraShakeSnapshot>,
+}
+
+/// Entities of the same kind can stand in for each other when a snapshot is restored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum EntityKind {
+    Player,
+    Platform,
+    Camera,
+    Body,
+    Projectile,
+}
+
+impl EntitySnapshot {
+    fn kind(&self) -> Option<EntityKind> {
+        if self.player.is_some() {
+            Some(EntityKind::Player)
+        } else if self.platform_time.is_some() {
+            Some(EntityKind::Platform)
+        } else if self.camera_shake.is_some() {
+            Some(EntityKind::Camera)
+        } else if self.projectile.is_some() {
+            Some(EntityKind::Projectile)
+        } else if self.rigid_body.is_some() {
+            Some(EntityKind::Body)
+        } else {
+            None
+        }
+    }
+
+    /// Spawns a body that was despawned after the snapshot was taken, `add_ball_meshes` gives it its mesh back
+    fn respawn(&self, world: &mut World) -> Option<Entity> {
+        let (rigid_body, collider) = (self.rigid_body.as_ref()?, self.collider.as_ref()?);
+        let position = from_array(rigid_body.position);
+
+        let entity = world.spawn((
+            Transform::from_translation(position),
+            RigidBody {
+                mass: rigid_body.mass,
+                cor: rigid_body.cor,
+                force: from_array(rigid_body.force),
+                velocity: from_array(rigid_body.velocity),
+                position,
+            },
+            Collider {
+                sphere: Sphere::new(from_array(collider.center), collider.radius),
+            },
+        ));
+
+        // the remaining state is written by `restore` like for every other entity
+        if let Some(gravity) = self.gravity {
+            let _ = world.insert_one(entity, Gravity(from_array(gravity)));
+        }
+        if let Some(lifetime) = self.lifetime {
+            let _ = world.insert_one(entity, Lifetime(lifetime));
+        }
+        if let Some(projectile) = &self.projectile {
+            let _ = world.insert_one(entity, Projectile {
+                shooter: Entity::from_bits(projectile.shooter),
+                definition: projectile.definition.clone(),
+                bounces: projectile.bounces,
+                age: projectile.age,
+                last_position: from_array(projectile.last_position),
+                touching: projectile.touching,
+            });
+        }
+
+        Some(entity)
+    }
+}
+
+/// The complete simulation state at a tick boundary
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PhysicsSnapshot {
+    pub tick: u64,
+    pub entities: Vec<EntitySnapshot>,
+}
+
+impl PhysicsSnapshot {
+    pub fn capture(world: &World, resources: &Resources) -> Self {
+        let mut entities: HashMap<Entity, EntitySnapshot> = HashMap::new();
+
+        for (entity, rb) in world.query::<(Entity, &RigidBody)>() {
+            entities.entry(entity).or_default().rigid_body = Some(RigidBodySnapshot {
+                mass: rb.mass,
+                cor: rb.cor,
+                force: to_array(rb.force),
+                velocity: to_array(rb.velocity),
+                position: to_array(rb.position),
+            });
+        }
+
+        for (entity, collider) in world.query::<(Entity, &Collider)>() {
+            entities.entry(entity).or_default().collider = Some(ColliderSnapshot {
+                center: to_array(collider.sphere.center),
+                radius: collider.sphere.radius,
+            });
+        }
+
+        for (entity, gravity) in world.query::<(Entity, &Gravity)>() {
+            entities.entry(entity).or_default().gravity = Some(to_array(gravity.0));
+        }
+
+        for (entity, lifetime) in world.query::<(Entity, &Lifetime)>() {
+            entities.entry(entity).or_default().lifetime = Some(lifetime.0);
+        }
+
+        for (entity, grounded_state) in world.query::<(Entity, &GroundedState)>() {
+            entities.entry(entity).or_default().grounded_state = Some(GroundedStateSnapshot {
+                is_grounded: grounded_state.is_grounded,
+                was_grounded: grounded_state.was_grounded,
+                is_on_slope: grounded_state.is_on_slope,
+                was_on_slope: grounded_state.was_on_slope,
+                frames_since_grounded: grounded_state.frames_since_grounded,
+                ground_normal: to_array(grounded_state.ground_normal),
+                slope_angle: grounded_state.slope_angle,
+                ground_instance: grounded_state.ground_instance.map(|id| id.0),
+                ground_rotation: grounded_state.ground_rotation,
+            });
+        }
+
+        for (entity, movement) in world.query::<(Entity, &Movement)>() {
+            entities.entry(entity).or_default().movement = Some(to_array(movement.0));
+        }
+
+        for (entity, _, transform) in world.query::<(Entity, &Kinematic, &Transform)>() {
+            entities.entry(entity).or_default().translation = Some(to_array(transform.translation));
+        }
+
+        for (entity, velocity) in world.query::<(Entity, &CharacterVelocity)>() {
+            entities.entry(entity).or_default().velocity = Some(to_array(velocity.0));
+        }
+
+        for (entity, jump_state) in world.query::<(Entity, &JumpState)>() {
+            entities.entry(entity).or_default().jump_state = Some(JumpStateSnapshot {
+                buffered_frames: jump_state.buffered_frames,
+                is_jumping: jump_state.is_jumping,
+            });
+        }
+
+        for (entity, mode) in world.query::<(Entity, &MovementMode)>() {
+            entities.entry(entity).or_default().movement_mode = Some(*mode);
+        }
+
+        for (entity, crouch_state, movement_data) in world.query::<(Entity, &CrouchState, &MovementData)>() {
+            entities.entry(entity).or_default().crouch_state = Some(CrouchStateSnapshot {
+                is_crouching: crouch_state.is_crouching,
+                standing_radius: crouch_state.standing_radius,
+                radius: movement_data.radius,
+            });
+        }
+
+        for (entity, stamina) in world.query::<(Entity, &Stamina)>() {
+            entities.entry(entity).or_default().stamina = Some(StaminaSnapshot {
+                current: stamina.current,
+                regen_delay: stamina.regen_delay,
+                is_sprinting: stamina.is_sprinting,
+                exhausted: stamina.exhausted,
+            });
+        }
+
+        for (entity, ladder_state) in world.query::<(Entity, &LadderState)>() {
+            entities.entry(entity).or_default().ladder_state = Some(LadderStateSnapshot {
+                ladder: ladder_state.ladder.map(|ladder| ladder.to_bits()),
+                normal: to_array(ladder_state.normal),
+                regrab_frames: ladder_state.regrab_frames,
+            });
+        }
+
+        for (entity, platform) in world.query::<(Entity, &MovingPlatform)>() {
+            entities.entry(entity).or_default().platform_time = Some(platform.time);
+        }
+
+        for (entity, player) in world.query::<(Entity, &Player)>() {
+            entities.entry(entity).or_default().player = Some(PlayerSnapshot {
+                yaw: player.yaw,
+                pitch: player.pitch,
+                pending_look: [player.pending_look.x(), player.pending_look.y()],
+            });
+        }
+
+        for (entity, projectile) in world.query::<(Entity, &Projectile)>() {
+            entities.entry(entity).or_default().projectile = Some(ProjectileSnapshot {
+                shooter: projectile.shooter.to_bits(),
+                definition: projectile.definition.clone(),
+                bounces: projectile.bounces,
+                age: projectile.age,
+                last_position: to_array(projectile.last_position),
+                touching: projectile.touching,
+            });
+        }
+
+        for (entity, weapons) in world.query::<(Entity, &Weapons)>() {
+            entities.entry(entity).or_default().weapons = Some(WeaponsSnapshot {
+                slots: weapons.slots.iter().map(|slot| WeaponSlotSnapshot {
+                    ammo: slot.ammo,
+                    cooldown: slot.cooldown,
+                    reload: slot.reload,
+                }).collect(),
+                current: weapons.current,
+                spread_state: weapons.spread_state,
+            });
+        }
+
+        for (entity, shake) in world.query::<(Entity, &CameraShake)>() {
+            let (shakes, time) = shake.state();
+            entities.entry(entity).or_default().camera_shake = Some(CameraShakeSnapshot { shakes, time });
+        }
+
+        // hash map order is random, sorting keeps the snapshot comparable
+        let mut entities: Vec<EntitySnapshot> = entities.into_iter().map(|(entity, mut snapshot)| {
+            snapshot.entity = entity.to_bits();
+            snapshot
+        }).collect();
+        entities.sort_by_key(|snapshot| snapshot.entity);
+
+        Self {
+            tick: resources.get::<PhysicsTick>().map(|tick| tick.0).unwrap_or(0),
+            entities,
+        }
+    }
+
+    /// Finds the live entity of every snapshot entity. Entities that are still alive keep their id, players,
+    /// platforms and cameras that are not are matched in spawn order to the live ones nothing claimed, which also lines up a
+    /// snapshot saved by an earlier session. Missing bodies are respawned and bodies spawned since are despawned.
+    fn map_entities(&self, world: &mut World) -> HashMap<u64, Entity> {
+        let mut unclaimed: HashMap<EntityKind, Vec<Entity>> = HashMap::new();
+        for (entity, _) in world.query::<(Entity, &Player)>() {
+            unclaimed.entry(EntityKind::Player).or_default().push(entity);
+        }
+        for (entity, _) in world.query::<(Entity, &MovingPlatform)>() {
+            unclaimed.entry(EntityKind::Platform).or_default().push(entity);
+        }
+        for (entity, _) in world.query::<(Entity, &CameraShake)>() {
+            unclaimed.entry(EntityKind::Camera).or_default().push(entity);
+        }
+        for (entity, _, projectile) in world.query::<(Entity, &RigidBody, Option<&Projectile>)>() {
+            let kind = if projectile.is_some() { EntityKind::Projectile } else { EntityKind::Body };
+            unclaimed.entry(kind).or_default().push(entity);
+        }
+        for entities in unclaimed.values_mut() {
+            entities.sort_by_key(|entity| entity.to_bits());
+        }
+
+        let mut entities = HashMap::new();
+        for snapshot in &self.entities {
+            let candidates = match snapshot.kind() {
+                Some(kind) => unclaimed.entry(kind).or_default(),
+                None => {
+                    entities.insert(snapshot.entity, Entity::from_bits(snapshot.entity));
+                    continue;
+                }
+            };
+            if let Some(index) = candidates.iter().position(|entity| entity.to_bits() == snapshot.entity) {
+                entities.insert(snapshot.entity, candidates.remove(index));
+            }
+        }
+
+        for snapshot in &self.entities {
+            if entities.contains_key(&snapshot.entity) {
+                continue;
+            }
+            let entity = match snapshot.kind() {
+                Some(kind @ EntityKind::Player) | Some(kind @ EntityKind::Platform) | Some(kind @ EntityKind::Camera) => {
+                    let candidates = unclaimed.entry(kind).or_default();
+                    if candidates.is_empty() { None } else { Some(candidates.remove(0)) }
+                }
+                _ => snapshot.respawn(world),
+            };
+            if let Some(entity) = entity {
+                entities.insert(snapshot.entity, entity);
+            }
+        }
+
+        for kind in [EntityKind::Body, EntityKind::Projectile].iter() {
+            for entity in unclaimed.remove(kind).unwrap_or_default() {
+                let _ = world.despawn(entity);
+            }
+        }
+
+        entities
+    }
+
+    /// Writes the snapshot back, see `map_entities` for entities that were spawned or despawned in the meantime
+    pub fn restore(&self, world: &mut World, resources: &mut Resources) {
+        let entities = self.map_entities(world);
+        let live = |bits: u64| entities.get(&bits).copied().unwrap_or_else(|| Entity::from_bits(bits));
+
+        for snapshot in &self.entities {
+            let entity = match entities.get(&snapshot.entity) {
+                Some(entity) => *entity,
+                None => continue,
+            };
+
+            // a different loadout could only be restored in part, leave the whole entity alone instead
+            if let (Some(state), Ok(weapons)) = (&snapshot.weapons, world.get::<Weapons>(entity)) {
+                if weapons.slots.len() != state.slots.len() {
+                    println!("Snapshot has {} weapon slots for {:?} but it has {}, not restoring it", state.slots.len(), entity, weapons.slots.len());
+                    continue;
+                }
+            }
+
+            if let Some(state) = &snapshot.rigid_body {
+                if let Ok(mut rb) = world.get_mut::<RigidBody>(entity) {
+                    rb.mass = state.mass;
+                    rb.cor = state.cor;
+                    rb.force = from_array(state.force);
+                    rb.velocity = from_array(state.velocity);
+                    rb.position = from_array(state.position);
+                }
+                if let Ok(mut transform) = world.get_mut::<Transform>(entity) {
+                    transform.translation = from_array(state.position);
+                }
+            }
+
+            if let Some(state) = &snapshot.collider {
+                if let Ok(mut collider) = world.get_mut::<Collider>(entity) {
+                    collider.sphere = Sphere::new(from_array(state.center), state.radius);
+                }
+            }
+
+            if let Some(state) = &snapshot.gravity {
+                if let Ok(mut gravity) = world.get_mut::<Gravity>(entity) {
+                    gravity.0 = from_array(*state);
+                }
+            }
+
+            if let Some(state) = &snapshot.lifetime {
+                if let Ok(mut lifetime) = world.get_mut::<Lifetime>(entity) {
+                    lifetime.0 = *state;
+                }
+            }
+
+            if let Some(state) = &snapshot.grounded_state {
+                if let Ok(mut grounded_state) = world.get_mut::<GroundedState>(entity) {
+                    grounded_state.is_grounded = state.is_grounded;
+                    grounded_state.was_grounded = state.was_grounded;
+                    grounded_state.is_on_slope = state.is_on_slope;
+                    grounded_state.was_on_slope = state.was_on_slope;
+                    grounded_state.frames_since_grounded = state.frames_since_grounded;
+                    grounded_state.ground_normal = from_array(state.ground_normal);
+                    grounded_state.slope_angle = state.slope_angle;
+                    grounded_state.ground_instance = state.ground_instance.map(InstanceId);
+                    grounded_state.ground_rotation = state.ground_rotation;
+                }
+            }
+
+            if let Some(state) = &snapshot.movement {
+                if let Ok(mut movement) = world.get_mut::<Movement>(entity) {
+                    movement.0 = from_array(*state);
+                }
+            }
+
+            if let Some(state) = &snapshot.translation {
+                if let Ok(mut transform) = world.get_mut::<Transform>(entity) {
+                    transform.translation = from_array(*state);
+                }
+            }
+
+            if let Some(state) = &snapshot.velocity {
+                if let Ok(mut velocity) = world.get_mut::<CharacterVelocity>(entity) {
+                    velocity.0 = from_array(*state);
+                }
+            }
+
+            if let Some(state) = &snapshot.jump_state {
+                if let Ok(mut jump_state) = world.get_mut::<JumpState>(entity) {
+                    jump_state.buffered_frames = state.buffered_frames;
+                    jump_state.is_jumping = state.is_jumping;
+                }
+            }
+
+            if let Some(state) = &snapshot.movement_mode {
+                if let Ok(mut mode) = world.get_mut::<MovementMode>(entity) {
+                    *mode = *state;
+                }
+            }
+
+            if let Some(state) = &snapshot.crouch_state {
+                if let Ok(mut crouch_state) = world.get_mut::<CrouchState>(entity) {
+                    crouch_state.is_crouching = state.is_crouching;
+                    crouch_state.standing_radius = state.standing_radius;
+                }
+                if let Ok(mut movement_data) = world.get_mut::<MovementData>(entity) {
+                    movement_data.radius = state.radius;
+                }
+            }
+
+            if let Some(state) = &snapshot.stamina {
+                if let Ok(mut stamina) = world.get_mut::<Stamina>(entity) {
+                    stamina.current = state.current;
+                    stamina.regen_delay = state.regen_delay;
+                    stamina.is_sprinting = state.is_sprinting;
+                    stamina.exhausted = state.exhausted;
+                }
+            }
+
+            if let Some(state) = &snapshot.ladder_state {
+                if let Ok(mut ladder_state) = world.get_mut::<LadderState>(entity) {
+                    ladder_state.ladder = state.ladder.map(live);
+                    ladder_state.normal = from_array(state.normal);
+                    ladder_state.regrab_frames = state.regrab_frames;
+                }
+            }
+
+            if let Some(state) = &snapshot.platform_time {
+                let platform = world.get_mut::<MovingPlatform>(entity).ok().map(|mut platform| {
+                    platform.time = *state;
+                    platform.clone()
+                });
+                let transform = platform.and_then(|platform| {
+                    let mut transform = world.get_mut::<Transform>(entity).ok()?;
+                    platform.place(&mut transform);
+                    Some(*transform)
+                });
+
+                // riders are carried by the displacement of the instance, the jump back must not carry them
+                if let (Some(transform), Ok(mut collision_instance), Some(mut physics_world)) = (
+                    transform,
+                    world.get_mut::<CollisionInstance>(entity),
+                    resources.get_mut::<crate::physics::World>(),
+                ) {
+                    collision_instance.teleport(&mut physics_world, &transform);
+                }
+            }
+
+            if let Some(state) = &snapshot.player {
+                if let Ok(mut player) = world.get_mut::<Player>(entity) {
+                    player.yaw = state.yaw;
+                    player.pitch = state.pitch;
+                    player.pending_look = Vec2::new(state.pending_look[0], state.pending_look[1]);
+                }
+            }
+
+            if let Some(state) = &snapshot.projectile {
+                if let Ok(mut projectile) = world.get_mut::<Projectile>(entity) {
+                    projectile.shooter = live(state.shooter);
+                    projectile.definition = state.definition.clone();
+                    projectile.bounces = state.bounces;
+                    projectile.age = state.age;
+                    projectile.last_position = from_array(state.last_position);
+                    projectile.touching = state.touching;
+                }
+            }
+
+            if let Some(state) = &snapshot.weapons {
+                if let Ok(mut weapons) = world.get_mut::<Weapons>(entity) {
+                    for (slot, slot_state) in weapons.slots.iter_mut().zip(state.slots.iter()) {
+                        slot.ammo = slot_state.ammo;
+                        slot.cooldown = slot_state.cooldown;
+                        slot.reload = slot_state.reload;
+                    }
+                    weapons.current = state.current;
+                    weapons.spread_state = state.spread_state;
+                }
+            }
+
+            if let Some(state) = &snapshot.camera_shake {
+                if let Ok(mut shake) = world.get_mut::<CameraShake>(entity) {
+                    shake.set_state(&state.shakes, state.time);
+                }
+            }
+        }
+
+        // the teleported platforms are in their instances but not yet in the top level
+        if let Some(mut physics_world) = resources.get_mut::<crate::physics::World>() {
+            physics_world.update_top_level();
+        }
+
+        if let Some(mut tick) = resources.get_mut::<PhysicsTick>() {
+            tick.0 = self.tick;
+        }
+    }
+
+    /// Hash over the exact bit patterns of the snapshot
+    pub fn checksum(&self) -> u64 {
+        let mut checksum = Checksum::new();
+        checksum.write_u64(self.tick);
+
+        for snapshot in &self.entities {
+            checksum.write_u64(snapshot.entity);
+
+            if let Some(rb) = &snapshot.rigid_body {
+                checksum.write_f32(rb.mass);
+                checksum.write_f32(rb.cor);
+                checksum.write_array(&rb.force);
+                checksum.write_array(&rb.velocity);
+                checksum.write_array(&rb.position);
+            }
+            if let Some(collider) = &snapshot.collider {
+                checksum.write_array(&collider.center);
+                checksum.write_f32(collider.radius);
+            }
+            if let Some(gravity) = &snapshot.gravity {
+                checksum.write_array(gravity);
+            }
+            if let Some(lifetime) = &snapshot.lifetime {
+                checksum.write_u64(*lifetime as u64);
+            }
+            if let Some(grounded_state) = &snapshot.grounded_state {
+                checksum.write_u64(grounded_state.is_grounded as u64);
+                checksum.write_u64(grounded_state.was_grounded as u64);
+                checksum.write_u64(grounded_state.is_on_slope as u64);
+                checksum.write_u64(grounded_state.was_on_slope as u64);
+                checksum.write_u64(grounded_state.frames_since_grounded as u64);
+                checksum.write_array(&grounded_state.ground_normal);
+                checksum.write_f32(grounded_state.slope_angle);
+                checksum.write_u64(grounded_state.ground_instance.map(|id| id as u64 + 1).unwrap_or(0));
+                checksum.write_f32(grounded_state.ground_rotation);
+            }
+            if let Some(movement) = &snapshot.movement {
+                checksum.write_array(movement);
+            }
+            if let Some(translation) = &snapshot.translation {
+                checksum.write_array(translation);
+            }
+            if let Some(velocity) = &snapshot.velocity {
+                checksum.write_array(velocity);
+            }
+            if let Some(jump_state) = &snapshot.jump_state {
+                checksum.write_u64(jump_state.buffered_frames.map(|frames| frames as u64 + 1).unwrap_or(0));
+                checksum.write_u64(jump_state.is_jumping as u64);
+            }
+            if let Some(mode) = &snapshot.movement_mode {
+                checksum.write_u64(*mode as u64);
+            }
+            if let Some(crouch_state) = &snapshot.crouch_state {
+                checksum.write_u64(crouch_state.is_crouching as u64);
+                checksum.write_f32(crouch_state.standing_radius);
+                checksum.write_f32(crouch_state.radius);
+            }
+            if let Some(stamina) = &snapshot.stamina {
+                checksum.write_f32(stamina.current);
+                checksum.write_f32(stamina.regen_delay);
+                checksum.write_u64(stamina.is_sprinting as u64);
+                checksum.write_u64(stamina.exhausted as u64);
+            }
+            if let Some(ladder_state) = &snapshot.ladder_state {
+                checksum.write_u64(ladder_state.ladder.map(|ladder| ladder.wrapping_add(1)).unwrap_or(0));
+                checksum.write_array(&ladder_state.normal);
+                checksum.write_u64(ladder_state.regrab_frames as u64);
+            }
+            if let Some(platform_time) = &snapshot.platform_time {
+                checksum.write_f32(*platform_time);
+            }
+            if let Some(player) = &snapshot.player {
+                checksum.write_f32(player.yaw);
+                checksum.write_f32(player.pitch);
+                checksum.write_f32(player.pending_look[0]);
+                checksum.write_f32(player.pending_look[1]);
+            }
+            if let Some(projectile) = &snapshot.projectile {
+                checksum.write_u64(projectile.shooter);
+                let definition = &projectile.definition;
+                checksum.write_f32(definition.speed);
+                checksum.write_f32(definition.gravity_scale);
+                checksum.write_f32(definition.radius);
+                checksum.write_f32(definition.mass);
+                checksum.write_f32(definition.bounciness);
+                checksum.write_u64(definition.bounces as u64);
+                checksum.write_u64(definition.fuse.map(|fuse| fuse.to_bits() as u64 + 1).unwrap_or(0));
+                checksum.write_u64(definition.detonate_on_impact as u64);
+                checksum.write_array(&definition.color);
+                checksum.write_f32(definition.explosion.radius);
+                checksum.write_f32(definition.explosion.damage);
+                checksum.write_f32(definition.explosion.impulse);
+                checksum.write_f32(definition.explosion.shake);
+                checksum.write_u64(projectile.bounces as u64);
+                checksum.write_f32(projectile.age);
+                checksum.write_array(&projectile.last_position);
+                checksum.write_u64(projectile.touching as u64);
+            }
+            if let Some(weapons) = &snapshot.weapons {
+                checksum.write_u64(weapons.slots.len() as u64);
+                for slot in &weapons.slots {
+                    checksum.write_u64(slot.ammo as u64);
+                    checksum.write_f32(slot.cooldown);
+                    checksum.write_u64(slot.reload.map(|reload| reload.to_bits() as u64 + 1).unwrap_or(0));
+                }
+                checksum.write_u64(weapons.current as u64);
+                checksum.write_u64(weapons.spread_state);
+            }
+            if let Some(camera_shake) = &snapshot.camera_shake {
+                checksum.write_u64(camera_shake.shakes.len() as u64);
+                for (trauma, profile) in &camera_shake.shakes {
+                    checksum.write_f32(*trauma);
+                    checksum.write_u64(profile.frequency.to_bits());
+                    checksum.write_f32(profile.decay);
+                    checksum.write_f32(profile.power);
+                    checksum.write_f32(profile.yaw);
+                    checksum.write_f32(profile.pitch);
+                    checksum.write_f32(profile.roll);
+                }
+                checksum.write_u64(camera_shake.time.to_bits());
+            }
+        }
+
+        checksum.0
+    }
+
+    pub fn to_ron(&self) -> Result<String, Box<dyn Error>> {
+        Ok(ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?)
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(ron::de::from_str(source)?)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.to_ron()?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        Self::from_ron(&fs::read_to_string(path)?)
+    }
+}
+
+/// The snapshot taken by the last quick save
+#[derive(Default)]
+pub struct QuickSnapshot(pub Option<PhysicsSnapshot>);
+
+/// F5 captures the simulation and writes it to disk, F9 rolls back to it
+pub fn quick_save_and_restore(world: &mut World, resources: &mut Resources) {
+    let (save, restore) = match resources.get::<Input<KeyCode>>() {
+        Some(keyboard_input) => (keyboard_input.just_pressed(KeyCode::F5), keyboard_input.just_pressed(KeyCode::F9)),
+        None => return,
+    };
+
+    if save {
+        let snapshot = PhysicsSnapshot::capture(world, resources);
+        if let Err(error) = snapshot.save(SNAPSHOT_PATH) {
+            println!("Failed to write snapshot to {}: {}", SNAPSHOT_PATH, error);
+        }
+        println!("Captured snapshot at tick {} (checksum {:x})", snapshot.tick, snapshot.checksum());
+        resources.get_mut::<QuickSnapshot>().unwrap().0 = Some(snapshot);
+    }
+
+    if restore {
+        let snapshot = resources.get::<QuickSnapshot>().unwrap().0.clone();
+        if let Some(snapshot) = snapshot {
+            snapshot.restore(world, resources);
+            println!("Restored snapshot of tick {}", snapshot.tick);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use crate::{
+        lifetime::{Lifetime, reduce_lifetime},
+        movement::*,
+        physics::{self, primitive::Sphere, test_util},
+    };
+    use super::{EntitySnapshot, PhysicsSnapshot};
+
+    const TICKS: usize = 120;
+
+    fn create_simulation() -> (World, Resources, Schedule) {
+        let mut resources = Resources::default();
+        resources.insert(physics::create_world_from_triangles(test_util::floor(10.0)));
+        resources.insert(PhysicsTick::default());
+
+        let mut world = World::new();
+        for i in 0..4 {
+            let position = Vec3::new(i as f32 * 0.5, 1.0 + i as f32, 0.0);
+            world.spawn((
+                RigidBody {
+                    mass: 1.0 + i as f32,
+                    cor: 0.5,
+                    force: Vec3::zero(),
+                    velocity: Vec3::new(1.0, 0.0, 0.5),
+                    position,
+                },
+                Collider {
+                    sphere: Sphere::new(Vec3::zero(), 0.2),
+                },
+                Gravity(Vec3::new(0.0, -10.0, 0.0)),
+                Lifetime(1000 + i as u32),
+                Transform::from_translation(position),
+            ));
+        }
+
+        let mut schedule = Schedule::default();
+        schedule.add_stage("update");
+        schedule.add_system_to_stage("update", reduce_lifetime.system());
+        schedule.add_system_to_stage("update", apply_gravity.system());
+        schedule.add_system_to_stage("update", update_velocity.system());
+        schedule.add_system_to_stage("update", resolve_collisions.system());
+        schedule.add_system_to_stage("update", update_rigid_bodies.system());
+        schedule.add_system_to_stage("update", update_rigid_body_transforms.system());
+        schedule.add_system_to_stage("update", advance_physics_tick.system());
+        schedule.initialize(&mut world, &mut resources);
+
+        (world, resources, schedule)
+    }
+
+    fn run(ticks: usize, world: &mut World, resources: &mut Resources, schedule: &mut Schedule) {
+        for _ in 0..ticks {
+            schedule.run(world, resources);
+        }
+    }
+
+    #[test]
+    fn test_restore_replays_bitwise_identical() {
+        let (mut world, mut resources, mut schedule) = create_simulation();
+        run(TICKS, &mut world, &mut resources, &mut schedule);
+
+        let snapshot = PhysicsSnapshot::capture(&world, &resources);
+        run(TICKS, &mut world, &mut resources, &mut schedule);
+        let first_run = PhysicsSnapshot::capture(&world, &resources);
+
+        snapshot.restore(&mut world, &mut resources);
+        assert_eq!(PhysicsSnapshot::capture(&world, &resources), snapshot);
+
+        run(TICKS, &mut world, &mut resources, &mut schedule);
+        let second_run = PhysicsSnapshot::capture(&world, &resources);
+
+        assert_eq!(first_run.tick, (TICKS * 2) as u64);
+        assert_eq!(first_run.checksum(), second_run.checksum());
+        assert_eq!(first_run, second_run);
+    }
+
+    /// entity ids change when a body is respawned, order by mass instead which is unique per body
+    fn without_ids(snapshot: &PhysicsSnapshot) -> Vec<EntitySnapshot> {
+        let mut entities: Vec<EntitySnapshot> = snapshot.entities.iter().cloned().map(|mut entity| {
+            entity.entity = 0;
+            entity
+        }).collect();
+        entities.sort_by_key(|entity| entity.rigid_body.as_ref().map(|rb| rb.mass.to_bits()));
+        entities
+    }
+
+    #[test]
+    fn test_restore_respawns_despawned_bodies() {
+        let (mut world, mut resources, mut schedule) = create_simulation();
+        run(TICKS, &mut world, &mut resources, &mut schedule);
+
+        let snapshot = PhysicsSnapshot::capture(&world, &resources);
+        run(TICKS, &mut world, &mut resources, &mut schedule);
+        let first_run = PhysicsSnapshot::capture(&world, &resources);
+
+        let (despawned, _) = world.query::<(Entity, &RigidBody)>().next().unwrap();
+        world.despawn(despawned).unwrap();
+
+        snapshot.restore(&mut world, &mut resources);
+        assert_eq!(without_ids(&PhysicsSnapshot::capture(&world, &resources)), without_ids(&snapshot));
+
+        run(TICKS, &mut world, &mut resources, &mut schedule);
+        let second_run = PhysicsSnapshot::capture(&world, &resources);
+
+        assert_eq!(without_ids(&first_run), without_ids(&second_run));
+    }
+
+    #[test]
+    fn test_serialization_roundtrip() {
+        let (mut world, mut resources, mut schedule) = create_simulation();
+        run(TICKS, &mut world, &mut resources, &mut schedule);
+
+        let snapshot = PhysicsSnapshot::capture(&world, &resources);
+        let deserialized = PhysicsSnapshot::from_ron(&snapshot.to_ron().unwrap()).unwrap();
+
+        assert_eq!(snapshot.checksum(), deserialized.checksum());
+    }
+}
@@ -0,0 +1,393 @@
+use std::{collections::VecDeque, sync::Arc};
+use bevy::prelude::*;
+
+use crate::{
+    camera::{CameraRig, SpectatorTarget},
+    controls::{Action, ActionState},
+    math::{Ray, degrees_to_radians},
+    movement::{Collider, MovementData, RigidBody},
+    physics::{self, CollisionInstance, CollisionWorldReloaded, primitive::Sphere},
+    player::Player,
+};
+
+/// how far away towers can be placed
+const BUILD_RANGE: f32 = 12.0;
+/// steepest ground a tower can stand on
+const MAX_BUILD_SLOPE: f32 = 20.0;
+pub const TOWER_RADIUS: f32 = 0.6;
+pub const TOWER_HEIGHT: f32 = 2.0;
+/// gap between the ground and the spheres that check whether the tower fits
+const CLEARANCE: f32 = 0.05;
+/// size of the cells the enemy path is searched on
+const PATH_CELL_SIZE: f32 = 0.5;
+/// the path may go this far around the straight line from start to goal
+const PATH_MARGIN: f32 = 6.0;
+/// enemies need this much room, towers closer together than twice this block the way
+const ENEMY_RADIUS: f32 = 0.4;
+/// height above the path at which walls are searched
+const PATH_HEIGHT: f32 = 1.0;
+
+/// A placed tower, enemies can not walk through it
+#[derive(Debug, Clone)]
+pub struct Tower {
+    pub radius: f32,
+}
+
+/// Towers can only be built inside of these, authored in the level as volumes named `build_zone`
+#[derive(Debug, Clone)]
+pub struct BuildZone {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl BuildZone {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self {
+            min,
+            max,
+        }
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.cmpge(self.min).all() && point.cmple(self.max).all()
+    }
+}
+
+/// Where enemies walk from and to, placing a tower must leave a way between them
+#[derive(Debug, Clone)]
+pub struct EnemyPath {
+    pub start: Vec3,
+    pub goal: Vec3,
+}
+
+impl Default for EnemyPath {
+    fn default() -> Self {
+        Self {
+            start: Vec3::new(-10.0, 0.0, -11.0),
+            goal: Vec3::new(10.0, 0.0, -11.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlacementError {
+    /// nothing within range under the crosshair
+    NoGround,
+    /// on a platform or another tower
+    NotStaticGround,
+    TooSteep,
+    OutsideBuildZone,
+    OverlapsTower,
+    /// a wall, a rigid body or a player is in the way
+    Blocked,
+    /// enemies could not reach their goal anymore
+    BlocksPath,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Placement {
+    /// the foot of the tower
+    pub position: Vec3,
+    pub result: Result<(), PlacementError>,
+}
+
+#[derive(Debug, Default)]
+pub struct BuildMode {
+    pub active: bool,
+    /// where a tower would be placed this frame, `None` outside of build mode
+    pub placement: Option<Placement>,
+}
+
+/// Marks the preview of the tower that is about to be placed
+pub struct TowerGhost;
+
+pub fn is_flat_enough(normal: Vec3) -> bool {
+    normal.dot(Vec3::unit_y()) >= degrees_to_radians(MAX_BUILD_SLOPE).cos()
+}
+
+/// Cells around the enemy path that the level blocks, towers are checked against it without touching the `World` again
+#[derive(Debug)]
+pub struct PathGrid {
+    min: Vec3,
+    width: usize,
+    depth: usize,
+    blocked: Vec<bool>,
+}
+
+impl PathGrid {
+    pub fn new(world: &physics::World, path: &EnemyPath) -> Self {
+        let min = path.start.min(path.goal) - Vec3::new(PATH_MARGIN, 0.0, PATH_MARGIN);
+        let max = path.start.max(path.goal) + Vec3::new(PATH_MARGIN, 0.0, PATH_MARGIN);
+        let width = ((max.x() - min.x()) / PATH_CELL_SIZE).ceil() as usize;
+        let depth = ((max.z() - min.z()) / PATH_CELL_SIZE).ceil() as usize;
+        let height = path.start.y().max(path.goal.y()) + PATH_HEIGHT;
+
+        let mut grid = Self {
+            min: Vec3::new(min.x(), height, min.z()),
+            width,
+            depth,
+            blocked: Vec::with_capacity(width * depth),
+        };
+        for z in 0..depth {
+            for x in 0..width {
+                let blocked = world.collide_sphere(&Sphere::new(grid.center(x, z), ENEMY_RADIUS)).is_some();
+                grid.blocked.push(blocked);
+            }
+        }
+
+        grid
+    }
+
+    fn center(&self, x: usize, z: usize) -> Vec3 {
+        self.min + Vec3::new((x as f32 + 0.5) * PATH_CELL_SIZE, 0.0, (z as f32 + 0.5) * PATH_CELL_SIZE)
+    }
+
+    fn cell(&self, position: Vec3) -> (usize, usize) {
+        let x = ((position.x() - self.min.x()) / PATH_CELL_SIZE).max(0.0) as usize;
+        let z = ((position.z() - self.min.z()) / PATH_CELL_SIZE).max(0.0) as usize;
+        (x.min(self.width - 1), z.min(self.depth - 1))
+    }
+
+    /// whether enemies can still walk from the start to the goal around all `towers`, given as foot and radius
+    pub fn is_reachable(&self, path: &EnemyPath, towers: &[(Vec3, f32)]) -> bool {
+        let is_open = |x: usize, z: usize| {
+            let center = self.center(x, z);
+            !self.blocked[z * self.width + x] && towers.iter().all(|(position, radius)| {
+                let offset = Vec3::new(center.x() - position.x(), 0.0, center.z() - position.z());
+                offset.length() >= radius + ENEMY_RADIUS
+            })
+        };
+
+        let start = self.cell(path.start);
+        let goal = self.cell(path.goal);
+        let mut visited = vec![false; self.width * self.depth];
+        let mut open = VecDeque::new();
+        visited[start.1 * self.width + start.0] = true;
+        open.push_back(start);
+
+        // breadth first, the start and goal themselves count as open
+        while let Some((x, z)) = open.pop_front() {
+            if (x, z) == goal {
+                return true;
+            }
+
+            let neighbours = [
+                (x.wrapping_sub(1), z),
+                (x + 1, z),
+                (x, z.wrapping_sub(1)),
+                (x, z + 1),
+            ];
+            for &(nx, nz) in neighbours.iter() {
+                if nx >= self.width || nz >= self.depth || visited[nz * self.width + nx] {
+                    continue;
+                }
+                if (nx, nz) == goal || is_open(nx, nz) {
+                    visited[nz * self.width + nx] = true;
+                    open.push_back((nx, nz));
+                }
+            }
+        }
+
+        false
+    }
+}
+
+/// whether a tower fits at `position` without touching the level or any of the `obstacles`, given as center and radius
+fn is_blocked(world: &physics::World, position: Vec3, obstacles: &[(Vec3, f32)]) -> bool {
+    let lower = position + Vec3::unit_y() * (TOWER_RADIUS + CLEARANCE);
+    let upper = position + Vec3::unit_y() * (TOWER_HEIGHT - TOWER_RADIUS);
+    let hits_level = [lower, upper].iter().any(|center| world.collide_sphere(&Sphere::new(*center, TOWER_RADIUS)).is_some());
+
+    let hits_obstacle = obstacles.iter().any(|(center, radius)| {
+        let horizontal_distance = Vec3::new(center.x() - position.x(), 0.0, center.z() - position.z()).length();
+        center.y() + radius >= position.y() && center.y() - radius <= position.y() + TOWER_HEIGHT && horizontal_distance < TOWER_RADIUS + radius
+    });
+
+    hits_level || hits_obstacle
+}
+
+/// the place under the crosshair and whether a tower can be built there
+fn find_placement(
+    world: &physics::World,
+    ray: &Ray,
+    grid: &PathGrid,
+    path: &EnemyPath,
+    zones: &[BuildZone],
+    towers: &[(Vec3, f32)],
+    obstacles: &[(Vec3, f32)],
+) -> Option<Placement> {
+    let hit = world.raycast(ray).filter(|hit| hit.t <= ray.length)?;
+    let position = hit.position;
+
+    let overlaps_tower = towers.iter().any(|(tower, radius)| {
+        Vec3::new(tower.x() - position.x(), 0.0, tower.z() - position.z()).length() < radius + TOWER_RADIUS
+    });
+
+    let result = if hit.instance.is_some() {
+        Err(PlacementError::NotStaticGround)
+    } else if !is_flat_enough(hit.normal) {
+        Err(PlacementError::TooSteep)
+    } else if !zones.iter().any(|zone| zone.contains(position)) {
+        Err(PlacementError::OutsideBuildZone)
+    } else if overlaps_tower {
+        Err(PlacementError::OverlapsTower)
+    } else if is_blocked(world, position, obstacles) {
+        Err(PlacementError::Blocked)
+    } else {
+        let mut towers = towers.to_vec();
+        towers.push((position, TOWER_RADIUS));
+        if grid.is_reachable(path, &towers) { Ok(()) } else { Err(PlacementError::BlocksPath) }
+    };
+
+    Some(Placement { position, result })
+}
+
+pub fn spawn_tower_ghost(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands
+        .spawn(PbrComponents {
+            mesh: meshes.add(Mesh::from(shape::Box::new(TOWER_RADIUS * 2.0, TOWER_HEIGHT, TOWER_RADIUS * 2.0))),
+            material: materials.add(Color::rgba(0.2, 0.8, 0.2, 0.4).into()),
+            draw: Draw {
+                is_visible: false,
+                is_transparent: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with(TowerGhost);
+}
+
+// --- Runs after the player looked around, before weapons fire ---
+
+/// toggles build mode, finds the placement under the crosshair and builds a tower on fire
+pub fn build_towers(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut grid: Local<Option<PathGrid>>,
+    mut reload_reader: Local<EventReader<CollisionWorldReloaded>>,
+    reload_events: Res<Events<CollisionWorldReloaded>>,
+    actions: Res<ActionState>,
+    world: Res<physics::World>,
+    path: Res<EnemyPath>,
+    mut build_mode: ResMut<BuildMode>,
+    rig_query: Query<&CameraRig>,
+    zone_query: Query<&BuildZone>,
+    tower_query: Query<(&Tower, &Transform)>,
+    body_query: Query<(&Collider, &RigidBody)>,
+    player_query: Query<(&Player, &Transform, &MovementData)>,
+) {
+    let actions = actions.for_player(&rig_query);
+
+    if actions.just_pressed(Action::Build) {
+        build_mode.active = !build_mode.active;
+        println!("Build mode {}", if build_mode.active { "on" } else { "off" });
+    }
+    if reload_reader.iter(&reload_events).next().is_some() {
+        *grid = None;
+    }
+    if !build_mode.active {
+        build_mode.placement = None;
+        return;
+    }
+
+    // walls only change when the level is reloaded, towers are checked every frame
+    let grid = grid.get_or_insert_with(|| PathGrid::new(&world, &path));
+    let zones: Vec<BuildZone> = zone_query.iter().cloned().collect();
+    let towers: Vec<(Vec3, f32)> = tower_query.iter()
+        .map(|(tower, transform)| (transform.translation - Vec3::unit_y() * TOWER_HEIGHT * 0.5, tower.radius))
+        .collect();
+    // rigid bodies and players, a tower on top of them would trap them
+    let mut obstacles: Vec<(Vec3, f32)> = body_query.iter().map(|(collider, rb)| (rb.position + collider.sphere.center, collider.sphere.radius)).collect();
+    obstacles.extend(player_query.iter().map(|(_, transform, movement_data)| (transform.translation, movement_data.radius)));
+
+    build_mode.placement = player_query.iter().next().and_then(|(player, transform, _)| {
+        let eye = transform.translation + Vec3::unit_y() * player.camera_height;
+        let ray = Ray::new(eye, player.get_look_direction(), BUILD_RANGE);
+        find_placement(&world, &ray, grid, &path, &zones, &towers, &obstacles)
+    });
+
+    if !actions.just_pressed(Action::Fire) {
+        return;
+    }
+
+    match build_mode.placement {
+        Some(Placement { position, result: Ok(()) }) => {
+            let size = Vec3::new(TOWER_RADIUS * 2.0, TOWER_HEIGHT, TOWER_RADIUS * 2.0);
+            commands
+                .spawn(PbrComponents {
+                    mesh: meshes.add(Mesh::from(shape::Box::new(size.x(), size.y(), size.z()))),
+                    material: materials.add(Color::rgb(0.4, 0.4, 0.6).into()),
+                    transform: Transform::from_translation(position + Vec3::unit_y() * TOWER_HEIGHT * 0.5),
+                    ..Default::default()
+                })
+                .with(Tower { radius: TOWER_RADIUS })
+                .with(SpectatorTarget::Tower)
+                .with(CollisionInstance::new(Arc::new(physics::create_box_mesh(size * -0.5, size * 0.5))));
+        },
+        Some(Placement { result: Err(error), .. }) => println!("Can not build here: {:?}", error),
+        None => println!("Can not build here: {:?}", PlacementError::NoGround),
+    }
+}
+
+/// moves the preview to the placement and tints it by whether the tower can be built
+pub fn update_tower_ghost(
+    build_mode: Res<BuildMode>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut ghost_query: Query<(&TowerGhost, &Handle<StandardMaterial>, &mut Draw, &mut Transform)>,
+) {
+    for (_, material, mut draw, mut transform) in ghost_query.iter_mut() {
+        draw.is_visible = build_mode.placement.is_some();
+
+        if let Some(placement) = build_mode.placement {
+            transform.translation = placement.position + Vec3::unit_y() * TOWER_HEIGHT * 0.5;
+            if let Some(material) = materials.get_mut(material) {
+                material.albedo = if placement.result.is_ok() { Color::rgba(0.2, 0.8, 0.2, 0.4) } else { Color::rgba(0.8, 0.2, 0.2, 0.4) };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::Vec3;
+
+    use crate::physics::{self, test_util};
+    use super::*;
+
+    fn create_floor_world() -> physics::World {
+        physics::create_world_from_triangles(test_util::floor(50.0))
+    }
+
+    #[test]
+    fn test_wall_of_towers_blocks_the_path() {
+        let world = create_floor_world();
+        let path = EnemyPath {
+            start: Vec3::new(-5.0, 0.0, 0.0),
+            goal: Vec3::new(5.0, 0.0, 0.0),
+        };
+        let grid = PathGrid::new(&world, &path);
+
+        // a line of towers across the whole search area
+        let wall: Vec<(Vec3, f32)> = (-5..=5).map(|i| (Vec3::new(0.0, 0.0, i as f32 * 1.5), TOWER_RADIUS)).collect();
+        assert!(grid.is_reachable(&path, &[]));
+        assert!(!grid.is_reachable(&path, &wall));
+
+        let with_gap: Vec<(Vec3, f32)> = wall.iter().cloned().filter(|(position, _)| position.z() != 0.0).collect();
+        assert!(grid.is_reachable(&path, &with_gap));
+    }
+
+    #[test]
+    fn test_only_flat_ground_in_build_zones() {
+        assert!(is_flat_enough(Vec3::unit_y()));
+        assert!(!is_flat_enough(Vec3::new(1.0, 1.0, 0.0).normalize()));
+
+        let zone = BuildZone::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        assert!(zone.contains(Vec3::zero()));
+        assert!(!zone.contains(Vec3::new(0.0, 0.0, 2.0)));
+    }
+}
@@ -19,14 +19,38 @@ pub fn draw_line(from_to: (Vec3, Vec3)) -> (Vec3, Vec3) {
 
 ///draw a worldspace line for a certain amount of frames before it will disappear
 pub fn draw_line_for(from_to: (Vec3, Vec3), for_frames: u32) -> (Vec3, Vec3) {
+    draw_colored_line_for(from_to, DEFAULT_COLOR, for_frames)
+}
+
+///draw a worldspace line in a color for a certain amount of frames
+pub fn draw_colored_line_for(from_to: (Vec3, Vec3), color: Color, for_frames: u32) -> (Vec3, Vec3) {
     let mut scheduled = SCHEDULED_PRIMITIVES.lock().unwrap();
     scheduled.push(Primitive {
         shape: Shape::Line(from_to), 
+        color,
         frames_left: for_frames,
     });
     from_to
 }
 
+///draw the 12 edges of an axis aligned box for a single frame
+pub fn draw_box(min: Vec3, max: Vec3, color: Color) {
+    let corner = |x: bool, y: bool, z: bool| Vec3::new(
+        if x { max.x() } else { min.x() },
+        if y { max.y() } else { min.y() },
+        if z { max.z() } else { min.z() },
+    );
+
+    for &(a, b) in &[(false, false), (true, false), (false, true), (true, true)] {
+        draw_colored_line_for((corner(false, a, b), corner(true, a, b)), color, 1);
+        draw_colored_line_for((corner(a, false, b), corner(a, true, b)), color, 1);
+        draw_colored_line_for((corner(a, b, false), corner(a, b, true)), color, 1);
+    }
+}
+
+//perfectly normal rgb
+const DEFAULT_COLOR: Color = Color::rgb(0.0, 0.0, 1000.0);
+
 ///a list of primitives which are about to be spawned as entities in the next invocation of `update_primitives`
 static SCHEDULED_PRIMITIVES: Lazy<Mutex<Vec<Primitive>>> = Lazy::new(|| Mutex::new(Vec::new()));
 
@@ -82,6 +106,7 @@ enum Shape {
 #[derive(Debug)]
 pub struct Primitive {
     shape: Shape,
+    color: Color,
     frames_left: u32, //how many frames the primitive is still visible
 }
 
@@ -90,7 +115,7 @@ pub struct Primitive {
 pub struct State {
     new_primitives: Vec::<Primitive>, //reused buffer for swapping with SCHEDULED_PRIMITIVES
     line_cube_mesh: Handle<Mesh>,
-    line_material: Handle<StandardMaterial>,
+    line_materials: Vec<(Color, Handle<StandardMaterial>)>, //one material per color that was drawn so far
 }
 
 /// only purpose of this system is to init the shared State, 
@@ -100,7 +125,7 @@ pub fn setup_primitives(mut commands: Commands,
     mut materials: ResMut<Assets<StandardMaterial>>) {
     let state = State {
         line_cube_mesh: meshes.add(Mesh::from(shape::Cube{size: 0.5})),
-        line_material: materials.add(Color::rgb(0.0, 0.0, 1000.0).into()), //perfectly normal rgb
+        line_materials: vec![(DEFAULT_COLOR, materials.add(DEFAULT_COLOR.into()))],
         ..Default::default()
     };
     commands.spawn((state, ));
@@ -110,6 +135,7 @@ pub fn setup_primitives(mut commands: Commands,
 /// updates entities by decreasing their "frames_left" value. Once it hits 0, the entity is despawned
 pub fn update_primitives(
     mut commands: Commands,
+    mut materials: ResMut<Assets<StandardMaterial>>,
     mut state: Query<&mut State>,
     mut primitives: Query<(Entity, &mut Primitive)>) {
 
@@ -117,13 +143,22 @@ pub fn update_primitives(
     .expect("draw_primitives::State was not initialized, did you forget to register the `setup_primitives` system?");
 
     let line_cube_mesh = state.line_cube_mesh.clone(); //because partial borrows...
-    let line_material  = state.line_material.clone();
 
     //fetch the scheduled primitives from the static mutex
     swap_sheduled_primitives(&mut state.new_primitives);
 
     //spawn a new entity for every primitive
-    for primitive in state.new_primitives.drain(..) {
+    let mut new_primitives = mem::take(&mut state.new_primitives);
+    for primitive in new_primitives.drain(..) {
+        let line_material = match state.line_materials.iter().find(|(color, _)| *color == primitive.color) {
+            Some((_, material)) => material.clone(),
+            None => {
+                let material = materials.add(primitive.color.into());
+                state.line_materials.push((primitive.color, material.clone()));
+                material
+            }
+        };
+
         let tf = match primitive.shape {
             Shape::Line(from_to) => {
                 //Transform::from_translation(from_to.0);
@@ -139,6 +174,7 @@ pub fn update_primitives(
         })
         .with(primitive);
     }
+    state.new_primitives = new_primitives; //hand the buffer back so it can be reused
     assert!(state.new_primitives.is_empty());
 
     // update existing primitives, despawn them if their time has come
@@ -0,0 +1,146 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::{ShakeEvent, ShakeProfile},
+    math::Ray,
+    movement::{Collider, RigidBody},
+    physics,
+    player::Player,
+};
+use super::{HitEvent, Projectile};
+
+/// cameras feel an explosion further away than it does damage
+const SHAKE_RADIUS_FACTOR: f32 = 3.0;
+/// geometry this close in front of a target does not shield it, the target itself may be touching it
+const OCCLUSION_TOLERANCE: f32 = 0.1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExplosionDefinition {
+    pub radius: f32,
+    /// damage right at the center, falls off linearly to 0 at the radius
+    pub damage: f32,
+    /// impulse given to rigid bodies right at the center
+    pub impulse: f32,
+    /// trauma added to cameras right at the center
+    pub shake: f32,
+}
+
+impl Default for ExplosionDefinition {
+    fn default() -> Self {
+        Self {
+            radius: 4.0,
+            damage: 80.0,
+            impulse: 12.0,
+            shake: 0.6,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExplosionEvent {
+    pub origin: Vec3,
+    pub shooter: Entity,
+    pub definition: ExplosionDefinition,
+}
+
+/// How much of an explosion at `origin` reaches `target`, `None` if it is out of range or behind geometry
+pub fn explosion_falloff(world: &physics::World, origin: Vec3, target: Vec3, radius: f32) -> Option<f32> {
+    let distance = (target - origin).length();
+    if distance >= radius {
+        return None;
+    }
+
+    if distance > std::f32::EPSILON {
+        let ray = Ray::new(origin, (target - origin) / distance, distance);
+        if world.raycast(&ray).map(|hit| hit.t < distance - OCCLUSION_TOLERANCE).unwrap_or(false) {
+            return None;
+        }
+    }
+
+    Some(1.0 - distance / radius)
+}
+
+// --- Runs after projectiles detonated ---
+
+/// damages players and rigid bodies around explosions and pushes the bodies away
+pub fn explode(
+    mut explosion_reader: Local<EventReader<ExplosionEvent>>,
+    explosion_events: Res<Events<ExplosionEvent>>,
+    world: Res<physics::World>,
+    mut hit_events: ResMut<Events<HitEvent>>,
+    mut shake_events: ResMut<Events<ShakeEvent>>,
+    player_query: Query<(Entity, &Player, &Transform)>,
+    mut body_query: Query<(Entity, &Collider, &mut RigidBody, Option<&Projectile>)>,
+) {
+    for explosion in explosion_reader.iter(&explosion_events) {
+        let definition = &explosion.definition;
+        let hit = |entity: Entity, target: Vec3, falloff: f32| {
+            let offset = target - explosion.origin;
+            let direction = if offset.length_squared() > std::f32::EPSILON { offset.normalize() } else { Vec3::unit_y() };
+            HitEvent {
+                shooter: explosion.shooter,
+                entity: Some(entity),
+                position: target,
+                // facing back towards the explosion like the surface a bullet hits
+                normal: -direction,
+                distance: offset.length(),
+                damage: definition.damage * falloff,
+            }
+        };
+
+        shake_events.send(ShakeEvent {
+            origin: explosion.origin,
+            intensity: definition.shake,
+            radius: definition.radius * SHAKE_RADIUS_FACTOR,
+            profile: ShakeProfile::default(),
+        });
+
+        for (entity, collider, mut rb, projectile) in body_query.iter_mut() {
+            // the projectile that exploded is despawned, others are not set off in a chain
+            if projectile.is_some() {
+                continue;
+            }
+
+            let center = rb.position + collider.sphere.center;
+            if let Some(falloff) = explosion_falloff(&world, explosion.origin, center, definition.radius) {
+                let event = hit(entity, center, falloff);
+                let velocity_change = -event.normal * definition.impulse * falloff / rb.mass;
+                rb.velocity += velocity_change;
+                hit_events.send(event);
+            }
+        }
+
+        for (entity, player, transform) in player_query.iter() {
+            // the middle of the body, feet behind a ledge still get hit
+            let center = transform.translation + Vec3::unit_y() * player.camera_height * 0.5;
+            if let Some(falloff) = explosion_falloff(&world, explosion.origin, center, definition.radius) {
+                hit_events.send(hit(entity, center, falloff));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::Vec3;
+
+    use crate::physics::{self, test_util};
+    use super::*;
+
+    #[test]
+    fn test_walls_block_explosions() {
+        // wall facing +x at x = 1
+        let world = physics::create_world_from_triangles(test_util::quad(
+            Vec3::new(1.0, -10.0, -10.0),
+            Vec3::new(1.0, 10.0, -10.0),
+            Vec3::new(1.0, 10.0, 10.0),
+            Vec3::new(1.0, -10.0, 10.0),
+        ));
+
+        assert_eq!(explosion_falloff(&world, Vec3::zero(), Vec3::new(2.0, 0.0, 0.0), 4.0), None);
+        assert_eq!(explosion_falloff(&world, Vec3::zero(), Vec3::new(-2.0, 0.0, 0.0), 4.0), Some(0.5));
+        assert_eq!(explosion_falloff(&world, Vec3::zero(), Vec3::new(-5.0, 0.0, 0.0), 4.0), None);
+    }
+}
@@ -0,0 +1,527 @@
+use std::{error::Error, fs};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ball::BallMeshes,
+    camera::{CameraEffects, CameraRig, MainCamera},
+    controls::{Action, ActionState},
+    math::{Ray, degrees_to_radians},
+    movement::{Collider, RigidBody},
+    physics::{self, CollisionInstance, primitive::Sphere},
+    player::Player,
+    tower::BuildMode,
+};
+
+mod explosion;
+mod physics_gun;
+mod projectile;
+
+pub use explosion::*;
+pub use physics_gun::*;
+pub use projectile::*;
+
+pub const WEAPONS_PATH: &str = "./assets/weapons.ron";
+
+/// seeds the spread of every player the same way, replays hit the same spots
+const SPREAD_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+/// frames the debug line at a hit stays visible
+const HIT_LINE_FRAMES: u32 = 60;
+
+/// How a weapon fires, loaded from `assets/weapons.ron`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WeaponDefinition {
+    pub name: String,
+    /// shots per second
+    pub fire_rate: f32,
+    /// keeps firing while the trigger is held
+    pub automatic: bool,
+    /// damage of every pellet
+    pub damage: f32,
+    pub range: f32,
+    /// degrees between the aim and the outermost pellet
+    pub spread: f32,
+    /// rays cast per shot
+    pub pellets: u32,
+    pub magazine_size: u32,
+    /// seconds until the magazine is full again
+    pub reload_time: f32,
+    /// degrees the view kicks up per shot
+    pub recoil: f32,
+    /// degrees the field of view widens per shot
+    pub fov_kick: f32,
+    /// launches a projectile per pellet instead of casting rays, `range` and `damage` are unused then
+    pub projectile: Option<ProjectileDefinition>,
+}
+
+impl Default for WeaponDefinition {
+    fn default() -> Self {
+        Self {
+            name: "Pistol".to_string(),
+            fire_rate: 4.0,
+            automatic: false,
+            damage: 25.0,
+            range: 100.0,
+            spread: 0.5,
+            pellets: 1,
+            magazine_size: 12,
+            reload_time: 1.2,
+            recoil: 1.5,
+            fov_kick: 2.0,
+            projectile: None,
+        }
+    }
+}
+
+/// Every weapon the player carries, in the order they are switched through
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WeaponDefinitions {
+    pub weapons: Vec<WeaponDefinition>,
+}
+
+impl Default for WeaponDefinitions {
+    fn default() -> Self {
+        Self {
+            weapons: vec![
+                WeaponDefinition::default(),
+                WeaponDefinition {
+                    name: "Shotgun".to_string(),
+                    fire_rate: 1.2,
+                    damage: 8.0,
+                    range: 30.0,
+                    spread: 6.0,
+                    pellets: 8,
+                    magazine_size: 6,
+                    reload_time: 2.0,
+                    recoil: 5.0,
+                    fov_kick: 4.0,
+                    ..Default::default()
+                },
+                WeaponDefinition {
+                    name: "Rifle".to_string(),
+                    fire_rate: 10.0,
+                    automatic: true,
+                    damage: 12.0,
+                    range: 150.0,
+                    spread: 1.5,
+                    magazine_size: 30,
+                    reload_time: 1.8,
+                    recoil: 0.8,
+                    fov_kick: 0.5,
+                    ..Default::default()
+                },
+                WeaponDefinition {
+                    name: "Grenade Launcher".to_string(),
+                    fire_rate: 1.5,
+                    spread: 0.0,
+                    magazine_size: 4,
+                    reload_time: 2.5,
+                    recoil: 3.0,
+                    fov_kick: 1.0,
+                    projectile: Some(ProjectileDefinition::default()),
+                    ..Default::default()
+                },
+                WeaponDefinition {
+                    name: "Rocket Launcher".to_string(),
+                    fire_rate: 1.0,
+                    spread: 0.0,
+                    magazine_size: 1,
+                    reload_time: 2.0,
+                    recoil: 4.0,
+                    fov_kick: 3.0,
+                    projectile: Some(ProjectileDefinition {
+                        speed: 30.0,
+                        gravity_scale: 0.0,
+                        radius: 0.12,
+                        bounces: 0,
+                        fuse: None,
+                        detonate_on_impact: true,
+                        color: [0.6, 0.2, 0.1],
+                        explosion: ExplosionDefinition {
+                            radius: 5.0,
+                            damage: 100.0,
+                            impulse: 18.0,
+                            shake: 0.8,
+                        },
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                WeaponDefinition {
+                    name: "Treat Lobber".to_string(),
+                    fire_rate: 3.0,
+                    spread: 2.0,
+                    magazine_size: 10,
+                    reload_time: 1.5,
+                    recoil: 0.5,
+                    fov_kick: 0.0,
+                    projectile: Some(ProjectileDefinition {
+                        speed: 10.0,
+                        radius: 0.1,
+                        mass: 0.2,
+                        bounciness: 0.3,
+                        bounces: 1,
+                        fuse: None,
+                        detonate_on_impact: true,
+                        color: [0.8, 0.6, 0.3],
+                        explosion: ExplosionDefinition {
+                            radius: 1.5,
+                            damage: 5.0,
+                            impulse: 1.0,
+                            shake: 0.0,
+                        },
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            ],
+        }
+    }
+}
+
+impl WeaponDefinitions {
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(ron::de::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// the weapons file, or the built in weapons if it can not be read
+    pub fn load_or_default(path: &str) -> Self {
+        match Self::load(path) {
+            Ok(definitions) if !definitions.weapons.is_empty() => definitions,
+            Ok(_) => {
+                println!("Using default weapons, {} has none", path);
+                Self::default()
+            },
+            Err(error) => {
+                println!("Using default weapons, could not read {}: {}", path, error);
+                Self::default()
+            },
+        }
+    }
+}
+
+/// A carried weapon and how much it has left to fire
+#[derive(Debug, Clone)]
+pub struct WeaponSlot {
+    pub definition: WeaponDefinition,
+    pub ammo: u32,
+    /// seconds until the next shot, can carry a little over into the next frame so the fire rate does not depend on the frame rate
+    pub(crate) cooldown: f32,
+    /// seconds until the reload finishes
+    pub(crate) reload: Option<f32>,
+}
+
+impl WeaponSlot {
+    pub fn new(definition: WeaponDefinition) -> Self {
+        Self {
+            ammo: definition.magazine_size,
+            definition,
+            cooldown: 0.0,
+            reload: None,
+        }
+    }
+
+    pub fn is_reloading(&self) -> bool {
+        self.reload.is_some()
+    }
+
+    fn start_reload(&mut self) {
+        if self.reload.is_none() && self.ammo < self.definition.magazine_size {
+            self.reload = Some(self.definition.reload_time);
+            println!("Reloading {}", self.definition.name);
+        }
+    }
+}
+
+/// The weapons of a player, only the current one fires or reloads
+#[derive(Debug, Clone)]
+pub struct Weapons {
+    pub slots: Vec<WeaponSlot>,
+    pub current: usize,
+    /// state of the random generator behind the spread
+    pub(crate) spread_state: u64,
+}
+
+impl Weapons {
+    pub fn new(definitions: &WeaponDefinitions) -> Self {
+        Self {
+            slots: definitions.weapons.iter().cloned().map(WeaponSlot::new).collect(),
+            current: 0,
+            spread_state: SPREAD_SEED,
+        }
+    }
+
+    pub fn current(&self) -> Option<&WeaponSlot> {
+        self.slots.get(self.current)
+    }
+
+    /// moves `offset` slots further, wrapping around. Switching away cancels a reload.
+    pub fn switch(&mut self, offset: isize) {
+        if self.slots.is_empty() {
+            return;
+        }
+
+        if let Some(slot) = self.slots.get_mut(self.current) {
+            slot.reload = None;
+        }
+        let count = self.slots.len() as isize;
+        self.current = (((self.current as isize + offset) % count + count) % count) as usize;
+
+        if let Some(slot) = self.current() {
+            println!("Switched to {} ({}/{})", slot.definition.name, slot.ammo, slot.definition.magazine_size);
+        }
+    }
+
+    /// Advances the current weapon by `delta_seconds` and returns how many shots it fired. Semi automatic weapons
+    /// only fire when the trigger was `just_pulled`, an empty magazine starts reloading on its own.
+    pub fn update(&mut self, delta_seconds: f32, trigger_held: bool, trigger_just_pulled: bool, reload: bool) -> u32 {
+        let slot = match self.slots.get_mut(self.current) {
+            Some(slot) => slot,
+            None => return 0,
+        };
+
+        if let Some(remaining) = slot.reload {
+            let remaining = remaining - delta_seconds;
+            if remaining <= 0.0 {
+                slot.ammo = slot.definition.magazine_size;
+                slot.reload = None;
+            } else {
+                slot.reload = Some(remaining);
+            }
+        }
+
+        if reload {
+            slot.start_reload();
+        }
+
+        let trigger = if slot.definition.automatic { trigger_held } else { trigger_just_pulled };
+        if slot.cooldown > 0.0 {
+            slot.cooldown -= delta_seconds;
+        }
+
+        let mut shots = 0;
+        if trigger {
+            while slot.cooldown <= 0.0 && slot.reload.is_none() && slot.ammo > 0 {
+                shots += 1;
+                slot.ammo -= 1;
+                slot.cooldown += 1.0 / slot.definition.fire_rate.max(std::f32::EPSILON);
+
+                if !slot.definition.automatic {
+                    break;
+                }
+            }
+
+            if slot.ammo == 0 {
+                slot.start_reload();
+            }
+        }
+
+        // an idle weapon is ready right away, it does not save up shots
+        slot.cooldown = slot.cooldown.max(0.0);
+        shots
+    }
+
+    /// a random direction within the spread of the current weapon around `aim`
+    pub fn spread_direction(&mut self, aim: Vec3) -> Vec3 {
+        let spread = match self.current() {
+            Some(slot) => degrees_to_radians(slot.definition.spread),
+            None => return aim,
+        };
+        if spread <= 0.0 {
+            return aim;
+        }
+
+        let side = aim.cross(Vec3::unit_y());
+        let side = if side.length_squared() > std::f32::EPSILON { side.normalize() } else { Vec3::unit_x() };
+        let up = side.cross(aim);
+
+        // uniformly distributed over the disc of the cone
+        let angle = self.next_random() * 2.0 * std::f32::consts::PI;
+        let radius = self.next_random().sqrt() * spread.tan();
+        (aim + side * angle.cos() * radius + up * angle.sin() * radius).normalize()
+    }
+
+    /// xorshift, deterministic so replays spread the same way
+    fn next_random(&mut self) -> f32 {
+        self.spread_state ^= self.spread_state << 13;
+        self.spread_state ^= self.spread_state >> 7;
+        self.spread_state ^= self.spread_state << 17;
+        (self.spread_state >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// A pellet that hit something, `entity` is `None` for the level geometry
+#[derive(Debug, Clone, Copy)]
+pub struct HitEvent {
+    pub shooter: Entity,
+    pub entity: Option<Entity>,
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub distance: f32,
+    pub damage: f32,
+}
+
+/// the closest hit along `ray`, against the level, collision instances and rigid body colliders
+pub fn raycast_entities<'a>(
+    world: &physics::World,
+    ray: &Ray,
+    instances: impl Iterator<Item = (Entity, &'a CollisionInstance)>,
+    bodies: impl Iterator<Item = (Entity, &'a Collider, &'a RigidBody)>,
+) -> Option<(physics::Intersection, Option<Entity>)> {
+    let mut best = world.raycast(ray).filter(|intersection| intersection.t <= ray.length).map(|intersection| {
+        let entity = intersection.instance.and_then(|id| {
+            instances.filter(|(_, instance)| instance.instance_id() == Some(id)).map(|(entity, _)| entity).next()
+        });
+        (intersection, entity)
+    });
+
+    for (entity, collider, body) in bodies {
+        let sphere = Sphere::new(body.position + collider.sphere.center, collider.sphere.radius);
+        if let Some(intersection) = sphere.intersects_ray(ray) {
+            if best.as_ref().map(|(closest, _)| intersection.t < closest.t).unwrap_or(true) {
+                best = Some((intersection, Some(entity)));
+            }
+        }
+    }
+
+    best
+}
+
+// --- Runs after the player looked around, before the camera effects are updated ---
+
+pub fn fire_weapons(
+    mut commands: Commands,
+    mut ball_meshes: ResMut<BallMeshes>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    actions: Res<ActionState>,
+    time: Res<Time>,
+    world: Res<physics::World>,
+    mut hit_events: ResMut<Events<HitEvent>>,
+    build_mode: Res<BuildMode>,
+    rig_query: Query<&CameraRig>,
+    instance_query: Query<(Entity, &CollisionInstance)>,
+    body_query: Query<(Entity, &Collider, &RigidBody)>,
+    mut camera_query: Query<(&MainCamera, &mut CameraEffects)>,
+    mut player_query: Query<(Entity, &Player, &Transform, &mut Weapons, Option<&PhysicsGun>)>,
+) {
+    let actions = actions.for_player(&rig_query);
+
+    for (shooter, player, transform, mut weapons, gun) in player_query.iter_mut() {
+        if actions.just_pressed(Action::NextWeapon) {
+            weapons.switch(1);
+        }
+        if actions.just_pressed(Action::PreviousWeapon) {
+            weapons.switch(-1);
+        }
+
+        // fire throws the body held by the physics gun or builds towers instead
+        let trigger = !gun.map(PhysicsGun::is_holding).unwrap_or(false) && !build_mode.active;
+        let shots = weapons.update(
+            time.delta_seconds,
+            trigger && actions.pressed(Action::Fire),
+            trigger && actions.just_pressed(Action::Fire),
+            actions.just_pressed(Action::Reload),
+        );
+        let definition = match weapons.current() {
+            Some(slot) => slot.definition.clone(),
+            None => continue,
+        };
+
+        let eye = transform.translation + Vec3::unit_y() * player.camera_height;
+        let aim = player.get_look_direction();
+
+        for _ in 0..shots {
+            for _ in 0..definition.pellets {
+                let direction = weapons.spread_direction(aim);
+                if let Some(projectile) = &definition.projectile {
+                    let handles = ball_meshes.get(&mut meshes, &mut materials, projectile.radius, projectile.color);
+                    spawn_projectile(&mut commands, handles, shooter, projectile, eye, direction);
+                    continue;
+                }
+
+                let ray = Ray::new(eye, direction, definition.range);
+                if let Some((intersection, entity)) = raycast_entities(&world, &ray, instance_query.iter(), body_query.iter()) {
+                    hit_events.send(HitEvent {
+                        shooter,
+                        entity,
+                        position: intersection.position,
+                        normal: intersection.normal,
+                        distance: intersection.t,
+                        damage: definition.damage,
+                    });
+                }
+            }
+
+            for (_, mut effects) in camera_query.iter_mut() {
+                effects.add_recoil(degrees_to_radians(definition.recoil), 0.0);
+                effects.kick_fov(degrees_to_radians(definition.fov_kick));
+            }
+        }
+    }
+}
+
+pub fn draw_hits(mut hit_reader: Local<EventReader<HitEvent>>, hit_events: Res<Events<HitEvent>>) {
+    for hit in hit_reader.iter(&hit_events) {
+        crate::util::draw_primitives::draw_line_for((hit.position, hit.position + hit.normal * 0.25), HIT_LINE_FRAMES);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rifle() -> Weapons {
+        let definitions = WeaponDefinitions::default();
+        let mut weapons = Weapons::new(&definitions);
+        weapons.current = definitions.weapons.iter().position(|weapon| weapon.automatic).unwrap();
+        weapons
+    }
+
+    fn shots_in_one_second(weapons: &mut Weapons, fps: u32) -> u32 {
+        let delta = 1.0 / fps as f32;
+        (0..fps).map(|frame| weapons.update(delta, true, frame == 0, false)).sum()
+    }
+
+    #[test]
+    fn test_fire_rate_does_not_depend_on_frame_rate() {
+        let (mut slow, mut fast) = (rifle(), rifle());
+        let rate = slow.current().unwrap().definition.fire_rate as u32;
+
+        assert_eq!(shots_in_one_second(&mut slow, 30), rate);
+        assert_eq!(shots_in_one_second(&mut fast, 240), rate);
+    }
+
+    #[test]
+    fn test_empty_magazine_reloads() {
+        let mut weapons = rifle();
+        let definition = weapons.current().unwrap().definition.clone();
+
+        let mut shots = 0;
+        for _ in 0..=definition.magazine_size {
+            shots += weapons.update(1.0 / definition.fire_rate, true, false, false);
+        }
+        assert_eq!(shots, definition.magazine_size);
+        assert!(weapons.current().unwrap().is_reloading());
+
+        weapons.update(definition.reload_time, false, false, false);
+        assert_eq!(weapons.current().unwrap().ammo, definition.magazine_size);
+        assert_eq!(weapons.update(0.0, true, true, false), 1);
+    }
+
+    #[test]
+    fn test_switching_cancels_reload() {
+        let mut weapons = rifle();
+        weapons.update(0.0, true, true, false);
+        weapons.update(0.0, false, false, true);
+        assert!(weapons.current().unwrap().is_reloading());
+
+        let rifle_index = weapons.current;
+        weapons.switch(1);
+        weapons.switch(-1);
+        assert_eq!(weapons.current, rifle_index);
+        assert!(!weapons.current().unwrap().is_reloading());
+    }
+}
@@ -0,0 +1,248 @@
+use bevy::prelude::*;
+
+use crate::{
+    camera::CameraRig,
+    controls::{Action, ActionState, Axis},
+    math::Ray,
+    movement::{CharacterVelocity, Collider, Encumbrance, Gravity, RigidBody},
+    physics::{self, CollisionInstance},
+    player::Player,
+    tower::BuildMode,
+};
+use super::{Projectile, raycast_entities};
+
+/// how far away bodies can be picked up
+const GRAB_RANGE: f32 = 4.0;
+/// held bodies float this far in front of the eye, plus their radius
+const HOLD_DISTANCE: f32 = 1.5;
+/// bodies pushed further than this from where they should be are dropped
+const BREAK_DISTANCE: f32 = 2.5;
+/// spring and damper of the hold, critically damped
+const HOLD_STIFFNESS: f32 = 100.0;
+const HOLD_DAMPING: f32 = 20.0;
+/// the heaviest body that can be picked up
+const MAX_CARRY_MASS: f32 = 20.0;
+/// the most weight the gun carries, heavier bodies hang below where they are held
+const MAX_LIFT: f32 = 40.0;
+/// the most force the gun can push with, heavy bodies lag behind and can not be forced through walls
+const MAX_HOLD_FORCE: f32 = 400.0;
+/// speed the player loses when carrying the heaviest body
+const MAX_SLOWDOWN: f32 = 0.6;
+/// light bodies fly further with the same impulse
+const THROW_IMPULSE: f32 = 15.0;
+/// radians a held body turns per count of mouse motion
+const ROTATE_SENSITIVITY: f32 = 0.005;
+
+/// Picks up rigid bodies in front of the player
+#[derive(Debug)]
+pub struct PhysicsGun {
+    pub held: Option<Entity>,
+    hold_distance: f32,
+    /// mouse motion turns the held body instead of the view
+    rotating: bool,
+    /// rotation input of this frame, applied to the held body by `rotate_held_bodies`
+    rotation: Quat,
+}
+
+impl Default for PhysicsGun {
+    fn default() -> Self {
+        Self {
+            held: None,
+            hold_distance: HOLD_DISTANCE,
+            rotating: false,
+            rotation: Quat::identity(),
+        }
+    }
+}
+
+impl PhysicsGun {
+    pub fn is_holding(&self) -> bool {
+        self.held.is_some()
+    }
+
+    pub fn is_rotating(&self) -> bool {
+        self.rotating
+    }
+
+    fn drop_held(&mut self) {
+        self.held = None;
+        self.rotating = false;
+    }
+}
+
+/// how fast the player can still move while carrying `mass`
+pub fn carry_speed_factor(mass: f32) -> f32 {
+    1.0 - (mass / MAX_CARRY_MASS).min(1.0) * MAX_SLOWDOWN
+}
+
+/// spring force pulling a body at `position` towards `target`, the spring has to hold whatever weight the gun does not lift
+pub fn hold_force(position: Vec3, velocity: Vec3, target: Vec3, target_velocity: Vec3, mass: f32, gravity: Vec3) -> Vec3 {
+    let acceleration = (target - position) * HOLD_STIFFNESS + (target_velocity - velocity) * HOLD_DAMPING;
+    let lift = if gravity.length() > MAX_LIFT { gravity.normalize() * MAX_LIFT } else { gravity };
+    let force = acceleration * mass - lift;
+    if force.length() > MAX_HOLD_FORCE {
+        force.normalize() * MAX_HOLD_FORCE
+    } else {
+        force
+    }
+}
+
+// --- Runs after the actions are updated and before the player looks around ---
+
+/// grabs the body in front of the player or drops the held one, and reads the rotation input
+pub fn grab_bodies(
+    time: Res<Time>,
+    actions: Res<ActionState>,
+    world: Res<physics::World>,
+    rig_query: Query<&CameraRig>,
+    instance_query: Query<(Entity, &CollisionInstance)>,
+    body_query: Query<(Entity, &Collider, &RigidBody, Option<&Projectile>)>,
+    mut player_query: Query<(&Player, &Transform, &mut PhysicsGun)>,
+) {
+    let actions = actions.for_player(&rig_query);
+
+    for (player, transform, mut gun) in player_query.iter_mut() {
+        // the held body may have been despawned
+        if let Some(held) = gun.held {
+            if body_query.get(held).is_err() {
+                gun.drop_held();
+            }
+        }
+
+        if actions.just_pressed(Action::Interact) {
+            if gun.is_holding() {
+                gun.drop_held();
+            } else {
+                let eye = transform.translation + Vec3::unit_y() * player.camera_height;
+                let ray = Ray::new(eye, player.get_look_direction(), GRAB_RANGE);
+                let bodies = body_query.iter().filter(|(.., projectile)| projectile.is_none()).map(|(entity, collider, rb, _)| (entity, collider, rb));
+
+                if let Some((_, Some(entity))) = raycast_entities(&world, &ray, instance_query.iter(), bodies) {
+                    if let Ok((_, collider, rb, _)) = body_query.get(entity) {
+                        if rb.mass <= MAX_CARRY_MASS {
+                            gun.held = Some(entity);
+                            gun.hold_distance = HOLD_DISTANCE + collider.sphere.radius;
+                        } else {
+                            println!("Too heavy to carry ({} of at most {})", rb.mass, MAX_CARRY_MASS);
+                        }
+                    }
+                }
+            }
+        }
+
+        gun.rotating = gun.is_holding() && actions.pressed(Action::RotateHeld);
+        gun.rotation = if gun.rotating {
+            // turns around the up axis and the side of the view like the mouse turns the camera
+            let counts = Vec2::new(actions.axis(Axis::LookX), actions.axis(Axis::LookY));
+            let turn = Vec2::new(actions.axis(Axis::TurnX), actions.axis(Axis::TurnY)) * time.delta_seconds;
+            let angles = counts * ROTATE_SENSITIVITY + turn;
+            let side = player.get_look_direction().cross(Vec3::unit_y());
+            let side = if side.length_squared() > std::f32::EPSILON { side.normalize() } else { Vec3::unit_x() };
+            Quat::from_axis_angle(side, -angles.y()) * Quat::from_axis_angle(Vec3::unit_y(), -angles.x())
+        } else {
+            Quat::identity()
+        };
+    }
+}
+
+// --- Runs once gravity is applied, before the velocity is updated ---
+
+/// pulls held bodies in front of the player and throws them on fire
+pub fn hold_bodies(
+    actions: Res<ActionState>,
+    build_mode: Res<BuildMode>,
+    rig_query: Query<&CameraRig>,
+    mut body_query: Query<(&mut RigidBody, Option<&Gravity>)>,
+    mut player_query: Query<(&Player, &Transform, Option<&CharacterVelocity>, &mut PhysicsGun, &mut Encumbrance)>,
+) {
+    let actions = actions.for_player(&rig_query);
+
+    for (player, transform, character_velocity, mut gun, mut encumbrance) in player_query.iter_mut() {
+        encumbrance.0 = 1.0;
+
+        let held = match gun.held {
+            Some(held) => held,
+            None => continue,
+        };
+        let (mut rb, gravity) = match body_query.get_mut(held) {
+            Ok(body) => body,
+            Err(_) => {
+                gun.drop_held();
+                continue;
+            },
+        };
+
+        let look = player.get_look_direction();
+        // fire builds towers in build mode
+        if actions.just_pressed(Action::Fire) && !build_mode.active {
+            let velocity_change = look * THROW_IMPULSE / rb.mass;
+            rb.velocity += velocity_change;
+            gun.drop_held();
+            continue;
+        }
+
+        let target = transform.translation + Vec3::unit_y() * player.camera_height + look * gun.hold_distance;
+        if (target - rb.position).length() > BREAK_DISTANCE {
+            gun.drop_held();
+            continue;
+        }
+
+        let target_velocity = character_velocity.map(|velocity| velocity.0).unwrap_or_else(Vec3::zero);
+        let gravity = gravity.map(|gravity| gravity.0).unwrap_or_else(Vec3::zero);
+        let force = hold_force(rb.position, rb.velocity, target, target_velocity, rb.mass, gravity);
+        rb.force += force;
+
+        encumbrance.0 = carry_speed_factor(rb.mass);
+    }
+}
+
+/// turns held bodies by the rotation input, bodies only simulate their position
+pub fn rotate_held_bodies(gun_query: Query<&PhysicsGun>, mut transform_query: Query<&mut Transform>) {
+    for gun in gun_query.iter() {
+        if let Some(held) = gun.held {
+            if let Ok(mut transform) = transform_query.get_mut(held) {
+                transform.rotation = (gun.rotation * transform.rotation).normalize();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::Vec3;
+
+    use super::*;
+
+    /// steps a body held at `target` with the same integration as the rigid bodies
+    fn settle(mass: f32, target: Vec3) -> Vec3 {
+        let gravity = Vec3::new(0.0, -10.0 * mass, 0.0);
+        let (mut position, mut velocity) = (Vec3::zero(), Vec3::zero());
+        // one physics tick
+        let delta = 0.016;
+        for _ in 0..200 {
+            let force = gravity + hold_force(position, velocity, target, Vec3::zero(), mass, gravity);
+            velocity += force / mass * delta;
+            position += velocity * delta;
+        }
+        position
+    }
+
+    #[test]
+    fn test_holds_light_bodies_in_place() {
+        let target = Vec3::new(0.0, 1.0, 1.0);
+        assert!((settle(1.0, target) - target).length() < 0.01);
+    }
+
+    #[test]
+    fn test_heavy_bodies_sag_and_slow_down() {
+        let target = Vec3::new(0.0, 1.0, 1.0);
+        // the heaviest body that can be picked up
+        let sag = target.y() - settle(MAX_CARRY_MASS, target).y();
+        assert!(sag > 0.05, "{}", sag);
+        assert!(sag < BREAK_DISTANCE, "{}", sag);
+
+        assert_eq!(carry_speed_factor(0.0), 1.0);
+        assert!(carry_speed_factor(MAX_CARRY_MASS * 0.5) > carry_speed_factor(MAX_CARRY_MASS));
+        assert_eq!(carry_speed_factor(MAX_CARRY_MASS * 2.0), 1.0 - MAX_SLOWDOWN);
+    }
+}
@@ -0,0 +1,159 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ball::spawn_ball,
+    math::Ray,
+    movement::{Collider, FIXED_UPDATE, RigidBody},
+    physics::{self, primitive::Sphere},
+};
+use super::{ExplosionDefinition, ExplosionEvent};
+
+/// projectiles that never detonate are removed after this many seconds
+const MAX_PROJECTILE_AGE: f32 = 20.0;
+/// a projectile counts as touching slightly before the collision response pushes it away
+const CONTACT_MARGIN: f32 = 1.1;
+/// spawned this far in front of the eye so it does not start inside the shooter
+const LAUNCH_OFFSET: f32 = 0.5;
+
+/// How a projectile weapon launches and how its projectile behaves in flight
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProjectileDefinition {
+    /// units per second along the aim
+    pub speed: f32,
+    /// 1 falls like everything else, 0 flies straight
+    pub gravity_scale: f32,
+    pub radius: f32,
+    pub mass: f32,
+    /// how much of its speed it keeps when bouncing off
+    pub bounciness: f32,
+    /// times it bounces off before an impact can detonate it
+    pub bounces: u32,
+    /// seconds until it detonates on its own, `None` waits for an impact
+    pub fuse: Option<f32>,
+    /// detonates on the first impact once all bounces are used up
+    pub detonate_on_impact: bool,
+    pub color: [f32; 3],
+    pub explosion: ExplosionDefinition,
+}
+
+impl Default for ProjectileDefinition {
+    fn default() -> Self {
+        Self {
+            speed: 18.0,
+            gravity_scale: 1.0,
+            radius: 0.15,
+            mass: 1.0,
+            bounciness: 0.5,
+            bounces: 3,
+            fuse: Some(2.5),
+            detonate_on_impact: false,
+            color: [0.3, 0.4, 0.3],
+            explosion: ExplosionDefinition::default(),
+        }
+    }
+}
+
+/// A launched projectile, it moves as a `RigidBody` and detonates through an `ExplosionEvent`
+#[derive(Debug, Clone)]
+pub struct Projectile {
+    pub shooter: Entity,
+    pub definition: ProjectileDefinition,
+    /// bounces so far
+    pub bounces: u32,
+    /// seconds since launch
+    pub age: f32,
+    /// where it was last tick, checked for walls it passed through since
+    pub(crate) last_position: Vec3,
+    /// touched something last tick, staying in contact does not count as another bounce
+    pub(crate) touching: bool,
+}
+
+/// `handles` are the mesh and material of the projectile, shared by everything fired with the same definition
+pub fn spawn_projectile(
+    commands: &mut Commands,
+    handles: (Handle<Mesh>, Handle<StandardMaterial>),
+    shooter: Entity,
+    definition: &ProjectileDefinition,
+    eye: Vec3,
+    direction: Vec3,
+) {
+    let position = eye + direction * LAUNCH_OFFSET;
+    let body = RigidBody {
+        force: Vec3::zero(),
+        mass: definition.mass,
+        cor: definition.bounciness,
+        position,
+        velocity: direction * definition.speed,
+    };
+
+    spawn_ball(commands, handles, body, definition.radius, definition.gravity_scale)
+        .with(Projectile {
+            shooter,
+            definition: definition.clone(),
+            bounces: 0,
+            age: 0.0,
+            last_position: position,
+            touching: false,
+        });
+}
+
+// --- Runs after the rigid bodies moved ---
+
+/// counts bounces and detonates projectiles whose fuse ran out or that hit something
+pub fn update_projectiles(
+    mut commands: Commands,
+    world: Res<physics::World>,
+    mut explosion_events: ResMut<Events<ExplosionEvent>>,
+    body_query: Query<(Entity, &Collider, &RigidBody)>,
+    mut projectile_query: Query<(Entity, &mut Projectile, &RigidBody)>,
+) {
+    for (entity, mut projectile, rb) in projectile_query.iter_mut() {
+        // the fuse burns in physics ticks, the same distance is covered before it goes off at any frame rate
+        projectile.age += FIXED_UPDATE;
+        let radius = projectile.definition.radius;
+
+        // fast projectiles can pass through thin walls within a single tick
+        let travel = rb.position - projectile.last_position;
+        let swept_hit = if travel.length_squared() > std::f32::EPSILON {
+            let ray = Ray::new(projectile.last_position, travel.normalize(), travel.length() + radius);
+            world.raycast(&ray).filter(|hit| hit.t <= ray.length)
+        } else {
+            None
+        };
+
+        let sphere = Sphere::new(rb.position, radius * CONTACT_MARGIN);
+        let touches_body = body_query.iter()
+            .filter(|(other, ..)| *other != entity && *other != projectile.shooter)
+            .any(|(_, collider, body)| sphere.overlaps(&Sphere::new(body.position + collider.sphere.center, collider.sphere.radius)));
+        let touching = swept_hit.is_some() || touches_body || world.collide_sphere(&sphere).is_some();
+
+        // resting or rolling along the ground is a single contact
+        let mut detonate = projectile.definition.fuse.map(|fuse| projectile.age >= fuse).unwrap_or(false);
+        if touching && !projectile.touching {
+            if projectile.bounces < projectile.definition.bounces {
+                projectile.bounces += 1;
+            } else if projectile.definition.detonate_on_impact {
+                detonate = true;
+            }
+        }
+        projectile.touching = touching;
+        projectile.last_position = rb.position;
+
+        if detonate {
+            let origin = match &swept_hit {
+                Some(hit) => hit.position + hit.normal * radius,
+                None => rb.position,
+            };
+            explosion_events.send(ExplosionEvent {
+                origin,
+                shooter: projectile.shooter,
+                definition: projectile.definition.explosion.clone(),
+            });
+            commands.despawn(entity);
+        } else if projectile.age > MAX_PROJECTILE_AGE {
+            commands.despawn(entity);
+        }
+    }
+}